@@ -0,0 +1,171 @@
+//! A bot speaking the Tetris Bot Protocol with no smarts at all, to try
+//! `tetris --tbp` without a real bot:
+//!
+//! `cargo build --example tbp_mock_bot && cargo run -- --tbp target/debug/examples/tbp_mock_bot`
+//!
+//! It keeps its own copy of the board from what the game tells it and always
+//! suggests dropping the falling piece pointing north, one column further to
+//! the right each time. Every message it gets is logged to stderr. The test
+//! in `tests/tbp.rs` plays against it.
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+
+use serde_json::{json, Value};
+
+const ROWS: usize = 40;
+const COLUMNS: i64 = 10;
+
+// the cells of each piece pointing north, `y` going up
+fn offsets(piece: char) -> [(i64, i64); 4] {
+    match piece {
+        'I' => [(-1, 0), (0, 0), (1, 0), (2, 0)],
+        'O' => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        'T' => [(-1, 0), (0, 0), (1, 0), (0, 1)],
+        'L' => [(-1, 0), (0, 0), (1, 0), (1, 1)],
+        'J' => [(-1, 0), (0, 0), (1, 0), (-1, 1)],
+        'S' => [(-1, 0), (0, 0), (0, 1), (1, 1)],
+        _ => [(-1, 1), (0, 1), (0, 0), (1, 0)],
+    }
+}
+
+fn turn(orientation: &str, (x, y): (i64, i64)) -> (i64, i64) {
+    match orientation {
+        "east" => (y, -x),
+        "south" => (-x, -y),
+        "west" => (-y, x),
+        _ => (x, y),
+    }
+}
+
+struct Mock {
+    // rows from the bottom
+    board: Vec<[bool; COLUMNS as usize]>,
+    queue: VecDeque<char>,
+    hold: Option<char>,
+    column: i64,
+}
+impl Mock {
+    fn taken(&self, x: i64, y: i64) -> bool {
+        !(0..COLUMNS).contains(&x)
+            || y < 0
+            || (y as usize) < ROWS && self.board[y as usize][x as usize]
+    }
+
+    fn fits(&self, piece: char, x: i64, y: i64) -> bool {
+        offsets(piece)
+            .iter()
+            .all(|(dx, dy)| !self.taken(x + dx, y + dy))
+    }
+
+    // the lowest place under the top the piece falls to in column `x`
+    fn drop(&self, piece: char, x: i64) -> Option<i64> {
+        let mut y = ROWS as i64 - 4;
+        if !self.fits(piece, x, y) {
+            return None;
+        }
+        while self.fits(piece, x, y - 1) {
+            y -= 1;
+        }
+        Some(y)
+    }
+
+    fn suggest(&mut self) -> Value {
+        let piece = self.queue.front().copied().unwrap_or('T');
+        let mut moves = vec![];
+        for n in 0..COLUMNS {
+            let x = (self.column + n) % COLUMNS;
+            if let Some(y) = self.drop(piece, x) {
+                moves.push(json!({
+                    "location": {"type": piece, "orientation": "north", "x": x, "y": y},
+                    "spin": "none",
+                }));
+            }
+        }
+        self.column = (self.column + 3) % COLUMNS;
+        json!({"type": "suggestion", "moves": moves})
+    }
+
+    fn play(&mut self, location: &Value) {
+        let piece = location["type"].as_str().and_then(|t| t.chars().next());
+        let orientation = location["orientation"].as_str().unwrap_or("north");
+        let (Some(piece), Some(x), Some(y)) =
+            (piece, location["x"].as_i64(), location["y"].as_i64())
+        else {
+            return;
+        };
+        // playing the kept piece swaps it with the falling one
+        if self.queue.front() != Some(&piece) {
+            let falling = self.queue.pop_front();
+            if self.hold.is_none() {
+                self.queue.pop_front();
+            }
+            self.hold = falling;
+        } else {
+            self.queue.pop_front();
+        }
+        for offset in offsets(piece) {
+            let (dx, dy) = turn(orientation, offset);
+            if let Some(row) = self.board.get_mut((y + dy) as usize) {
+                row[(x + dx) as usize] = true;
+            }
+        }
+        self.board.retain(|row| !row.iter().all(|cell| *cell));
+        self.board.resize(ROWS, [false; COLUMNS as usize]);
+    }
+}
+
+fn main() {
+    let mut mock = Mock {
+        board: vec![[false; COLUMNS as usize]; ROWS],
+        queue: VecDeque::new(),
+        hold: None,
+        column: 0,
+    };
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    let mut send = |message: Value| {
+        writeln!(stdout, "{}", message).unwrap();
+        stdout.flush().unwrap();
+    };
+    send(json!({
+        "type": "info",
+        "name": "Mock",
+        "version": "1.0",
+        "author": "tetris_rust",
+        "features": [],
+    }));
+    for line in stdin.lock().lines() {
+        let line = line.unwrap();
+        eprintln!("mock bot got {}", line);
+        let message: Value = serde_json::from_str(&line).unwrap();
+        match message["type"].as_str() {
+            Some("rules") => send(json!({"type": "ready"})),
+            Some("start") => {
+                mock.board = vec![[false; COLUMNS as usize]; ROWS];
+                let rows = message["board"].as_array().cloned().unwrap_or_default();
+                for (y, row) in rows.iter().enumerate().take(ROWS) {
+                    for (x, cell) in row.as_array().into_iter().flatten().enumerate() {
+                        mock.board[y][x] = !cell.is_null();
+                    }
+                }
+                let letter = |value: &Value| value.as_str().and_then(|s| s.chars().next());
+                mock.queue = message["queue"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(letter)
+                    .collect();
+                mock.hold = letter(&message["hold"]);
+            }
+            Some("suggest") => send(mock.suggest()),
+            Some("play") => mock.play(&message["move"]["location"]),
+            Some("new_piece") => mock.queue.extend(
+                message["piece"]
+                    .as_str()
+                    .and_then(|piece| piece.chars().next()),
+            ),
+            Some("quit") => break,
+            _ => (),
+        }
+    }
+}
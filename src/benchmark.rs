@@ -0,0 +1,142 @@
+//! `tetris --benchmark`: plays the same seeded games with the greedy bot and
+//! with a few lookaheads, with no window, to compare how well they play and
+//! how long they think. With no time budget the games only depend on the
+//! seeds, so two runs give the same lines and stacks.
+//!
+//! ```text
+//! tetris --benchmark [--games <n>] [--pieces <n>] [--nodes <n>] [--time-ms <ms>]
+//! ```
+use std::time::{Duration, Instant};
+
+use ggez::{GameError, GameResult};
+
+use crate::bot::{Bot, Lookahead};
+use crate::GameState;
+
+// The lookaheads compared with the greedy bot, as depth and beam width.
+const LOOKAHEADS: [(usize, usize); 4] = [(2, 4), (3, 8), (4, 16), (5, 32)];
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct BenchmarkOptions {
+    // games played by each bot, the seeds going from 0
+    pub games: u64,
+    // pieces each game lasts at most
+    pub pieces: u64,
+    // the boards each lookahead bot searches from for each figure, see
+    // `Lookahead::nodes`, none to wait for the whole search
+    pub nodes: Option<usize>,
+    // the time each lookahead bot has to think about each figure, none to
+    // wait for the whole search
+    pub time: Option<Duration>,
+}
+impl Default for BenchmarkOptions {
+    fn default() -> Self {
+        BenchmarkOptions {
+            games: 5,
+            pieces: 1000,
+            nodes: None,
+            time: None,
+        }
+    }
+}
+impl BenchmarkOptions {
+    /// Reads the options following `--benchmark`.
+    pub fn parse(args: &[String]) -> GameResult<Self> {
+        let mut options = BenchmarkOptions::default();
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let number = args
+                .next()
+                .and_then(|value| value.parse::<u64>().ok())
+                .filter(|n| *n > 0)
+                .ok_or_else(|| GameError::ConfigError(format!("{} needs a count", flag)))?;
+            match flag.as_str() {
+                "--games" => options.games = number,
+                "--pieces" => options.pieces = number,
+                "--nodes" => options.nodes = Some(number as usize),
+                "--time-ms" => options.time = Some(Duration::from_millis(number)),
+                _ => {
+                    return Err(GameError::ConfigError(format!(
+                        "unknown option `{}` for --benchmark",
+                        flag
+                    )))
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// How a bot did over all the games.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+struct Results {
+    lines: u32,
+    pieces: u64,
+    top_outs: u32,
+    // the stack heights when each piece locked, added up
+    heights: u64,
+    // time spent in the bot, in all and on the slowest frame
+    thinking: Duration,
+    slowest: Duration,
+}
+
+/// Plays the game of `seed` with `bot` as fast as it can.
+fn play(bot: &mut Bot, seed: u64, pieces: u64, results: &mut Results) {
+    let mut state = GameState::with_seed(seed);
+    let mut placed = state.seats[0].pieces;
+    while !state.gameover && state.seats[0].pieces < pieces {
+        let start = Instant::now();
+        let input = bot.input(&state);
+        let thought = start.elapsed();
+        results.thinking += thought;
+        results.slowest = results.slowest.max(thought);
+        if let Some(input) = input {
+            state.handle_input(input);
+        }
+        state.tick();
+        if state.seats[0].pieces != placed {
+            placed = state.seats[0].pieces;
+            results.heights += state.stack_height() as u64;
+        }
+    }
+    results.lines += state.score;
+    results.pieces += state.seats[0].pieces;
+    results.top_outs += state.gameover as u32;
+}
+
+/// Runs the benchmark, printing a line for each bot.
+pub fn benchmark(options: &BenchmarkOptions) -> GameResult {
+    let mut bots = vec![("greedy".to_string(), None)];
+    for (depth, beam) in LOOKAHEADS {
+        let lookahead = Lookahead {
+            depth,
+            beam,
+            nodes: options.nodes.unwrap_or(usize::MAX),
+            time: options.time,
+        };
+        bots.push((format!("depth {} beam {}", depth, beam), Some(lookahead)));
+    }
+    println!(
+        "{} games of up to {} pieces each",
+        options.games, options.pieces
+    );
+    for (name, lookahead) in bots {
+        let mut results = Results::default();
+        for seed in 0..options.games {
+            let mut bot = Bot::new(0);
+            bot.lookahead = lookahead;
+            play(&mut bot, seed, options.pieces, &mut results);
+        }
+        let pieces = results.pieces.max(1);
+        println!(
+            "{:<18} {:>6} lines {:>3} top outs  stack {:>5.2} high  {:>7.3} ms a piece, {:>7.3} ms at most",
+            name,
+            results.lines,
+            results.top_outs,
+            results.heights as f64 / pieces as f64,
+            results.thinking.as_secs_f64() * 1000.0 / pieces as f64,
+            results.slowest.as_secs_f64() * 1000.0
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,944 @@
+//! Bots: they look for the best place for the actual figure and press the keys
+//! that take it there, one every few frames like a player would.
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use ggez::{GameError, GameResult};
+use oorandom::Rand32;
+
+use crate::garbage::{attack, Clear};
+use crate::input::Input;
+use crate::{tspin_kind, Figure, GameState, Grid};
+
+/// The cells of a board taken by the stack, to try placements without going
+/// through the blocks every time.
+#[derive(Clone, PartialEq, Debug)]
+struct Cells {
+    grid: Grid,
+    taken: Vec<bool>,
+}
+impl Cells {
+    fn new(board: &GameState) -> Self {
+        let grid = board.grid;
+        let mut cells = Cells {
+            grid,
+            taken: vec![false; (grid.width * grid.height) as usize],
+        };
+        for block in board.static_blocks.iter() {
+            cells.set(block.x, block.y);
+        }
+        cells
+    }
+
+    fn set(&mut self, x: i16, y: i16) {
+        if !self.grid.ilegal_coords(x, y) && y >= 0 && y < self.grid.height {
+            self.taken[(y * self.grid.width + x) as usize] = true;
+        }
+    }
+
+    // walls and floor count as taken, the rows past the spawn row are free
+    fn is_taken(&self, x: i16, y: i16) -> bool {
+        if self.grid.ilegal_coords(x, y) {
+            true
+        } else if y < 0 || y >= self.grid.height {
+            false
+        } else {
+            self.taken[(y * self.grid.width + x) as usize]
+        }
+    }
+
+    /// `fig` lies on the floor or on the stack, so it locks on the next frame.
+    fn landed(&self, fig: &Figure) -> bool {
+        fig.blocks
+            .iter()
+            .any(|block| self.is_taken(block.x, block.y + self.grid.fall()))
+    }
+
+    fn fits(&self, fig: &Figure) -> bool {
+        fig.blocks
+            .iter()
+            .all(|block| !self.is_taken(block.x, block.y))
+    }
+
+    /// Where `fig` lands if it falls straight from where it is.
+    fn drop(&self, mut fig: Figure) -> Figure {
+        loop {
+            let mut next = fig;
+            for block in next.blocks.iter_mut() {
+                block.y += self.grid.fall();
+            }
+            if !self.fits(&next) {
+                return fig;
+            }
+            fig = next;
+        }
+    }
+
+    /// Locks `fig` and clears the full rows, returns how many there were.
+    fn lock(&mut self, fig: &Figure) -> u32 {
+        for block in fig.blocks.iter() {
+            self.set(block.x, block.y);
+        }
+        let width = self.grid.width as usize;
+        let rows: Vec<&[bool]> = self.taken.chunks(width).collect();
+        let kept: Vec<&[bool]> = rows
+            .iter()
+            .copied()
+            .filter(|row| !row.iter().all(|cell| *cell))
+            .collect();
+        let cleared = rows.len() - kept.len();
+        // the rows left fall towards the floor, whichever side that is
+        let empty = vec![false; width * cleared];
+        self.taken = if self.grid.upside_down {
+            kept.concat().into_iter().chain(empty).collect()
+        } else {
+            empty.into_iter().chain(kept.concat()).collect()
+        };
+        cleared as u32
+    }
+
+    /// Height of each column counted from the floor.
+    fn heights(&self) -> Vec<i16> {
+        (0..self.grid.width)
+            .map(|x| {
+                (0..self.grid.height)
+                    .filter(|y| self.taken[(y * self.grid.width + x) as usize])
+                    .map(|y| (self.grid.floor_row() - y) * self.grid.fall() + 1)
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+
+    /// Empty cells with some block between them and the spawn row.
+    fn holes(&self, heights: &[i16]) -> i16 {
+        (0..self.grid.width)
+            .map(|x| {
+                (0..self.grid.height)
+                    .filter(|y| {
+                        let height = (self.grid.floor_row() - y) * self.grid.fall() + 1;
+                        height < heights[x as usize]
+                            && !self.taken[(y * self.grid.width + x) as usize]
+                    })
+                    .count() as i16
+            })
+            .sum()
+    }
+
+    /// How deep the wells are in total, a well being a column lower than the
+    /// ones on both sides of it, the walls counting as high as the board.
+    fn wells(&self, heights: &[i16]) -> i16 {
+        let wall = self.grid.height;
+        (0..heights.len())
+            .map(|x| {
+                let left = if x == 0 { wall } else { heights[x - 1] };
+                let right = heights.get(x + 1).copied().unwrap_or(wall);
+                (left.min(right) - heights[x]).max(0)
+            })
+            .sum()
+    }
+}
+
+/// What the bot looks at in the stack a placement leaves, each feature times
+/// its weight adds to how good the placement is.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Weights {
+    pub lines: f32,
+    // the heights of all the columns added up
+    pub height: f32,
+    pub holes: f32,
+    // how much the heights change from one column to the next
+    pub bumpiness: f32,
+    pub wells: f32,
+    // the garbage the clears send, the higher the more the bot goes for
+    // tetrises and T-spins
+    pub attack: f32,
+}
+impl Weights {
+    /// Reads weights saved by `tetris --train`.
+    pub fn load<P: AsRef<Path>>(path: P) -> GameResult<Weights> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        Weights::parse(&text)
+            .map_err(|e| GameError::ResourceLoadError(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Reads weights written as `name: value` lines, like `Weights`' `Display`
+    /// writes them. The ones not given keep their default value.
+    pub fn parse(text: &str) -> Result<Weights, String> {
+        let mut weights = Weights::default();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once(':')
+                .ok_or(format!("line {}: expected `name: value`", n + 1))?;
+            let value: f32 = value
+                .trim()
+                .parse()
+                .map_err(|_| format!("line {}: `{}` is not a number", n + 1, value.trim()))?;
+            match key.trim() {
+                "lines" => weights.lines = value,
+                "height" => weights.height = value,
+                "holes" => weights.holes = value,
+                "bumpiness" => weights.bumpiness = value,
+                "wells" => weights.wells = value,
+                "attack" => weights.attack = value,
+                key => return Err(format!("line {}: unknown weight `{}`", n + 1, key)),
+            }
+        }
+        Ok(weights)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> GameResult {
+        Ok(fs::write(path, self.to_string())?)
+    }
+}
+impl fmt::Display for Weights {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "lines: {}", self.lines)?;
+        writeln!(f, "height: {}", self.height)?;
+        writeln!(f, "holes: {}", self.holes)?;
+        writeln!(f, "bumpiness: {}", self.bumpiness)?;
+        writeln!(f, "wells: {}", self.wells)?;
+        writeln!(f, "attack: {}", self.attack)
+    }
+}
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            lines: 0.76,
+            height: -0.51,
+            holes: -0.36,
+            bumpiness: -0.18,
+            wells: -0.1,
+            attack: 0.0,
+        }
+    }
+}
+
+/// How good the stack left by a placement is, higher is better.
+fn evaluate(cells: &Cells, cleared: u32, sent: u32, weights: &Weights) -> f32 {
+    let heights = cells.heights();
+    let total: i16 = heights.iter().sum();
+    let bumpiness: i16 = heights
+        .windows(2)
+        .map(|pair| (pair[0] - pair[1]).abs())
+        .sum();
+    cleared as f32 * weights.lines
+        + total as f32 * weights.height
+        + cells.holes(&heights) as f32 * weights.holes
+        + bumpiness as f32 * weights.bumpiness
+        + cells.wells(&heights) as f32 * weights.wells
+        + sent as f32 * weights.attack
+}
+
+/// A place a figure can lock in and the fewest inputs that take it there from
+/// where it is, it falls the rest of the way on its own.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Placement {
+    pub figure: Figure,
+    pub inputs: Vec<Input>,
+    // the last input turned the figure into place, as a T-spin needs
+    pub spin: bool,
+}
+
+// A position of the figure being searched: which of its four turns and where
+// its first block, the one it turns around, is.
+type State = (usize, i16, i16);
+// A placement found: the figure, its count of inputs, the position it's
+// reached from and the input that does it, none when it falls from there.
+type Found = (Figure, usize, State, Option<Input>);
+// How a position was first reached: its count of inputs and the position and
+// input before it, none for where the figure starts.
+type Reached = (usize, Option<(State, Input)>);
+
+/// `fig` with all its blocks moved by `dx` and `dy`.
+fn shifted(mut fig: Figure, dx: i16, dy: i16) -> Figure {
+    for block in fig.blocks.iter_mut() {
+        block.x += dx;
+        block.y += dy;
+    }
+    fig
+}
+
+/// Every place `fig` can lock in on `cells`, tucks and spins included, found
+/// by trying each input from each position the figure reaches before landing.
+/// The moves are the ones of `GameState::handle_seat_input`, worked out once
+/// for each turn of the figure instead of rotating its blocks every time.
+fn find_placements(cells: &Cells, fig: Figure) -> Vec<Placement> {
+    let grid = cells.grid;
+    if !cells.fits(&fig) {
+        return vec![];
+    }
+    if cells.landed(&fig) {
+        return vec![Placement {
+            figure: fig,
+            inputs: vec![],
+            spin: false,
+        }];
+    }
+    // the four turns of the figure, in the middle of the board where nothing
+    // stops them
+    let origin = (grid.width / 2, grid.height / 2);
+    let mut turns = [shifted(fig, origin.0 - fig.blocks[0].x, origin.1 - fig.blocks[0].y); 4];
+    for n in 1..4 {
+        turns[n] = turns[n - 1];
+        turns[n].rotate(grid);
+    }
+    let figure = |(turn, x, y): State| shifted(turns[turn], x - origin.0, y - origin.1);
+    // the first block stays a few rows past the board at most
+    let rows = grid.height + 8;
+    let index = |(turn, x, y): State| {
+        ((0..grid.width).contains(&x) && (-4..grid.height + 4).contains(&y))
+            .then(|| ((turn as i16 * grid.width + x) * rows + y + 4) as usize)
+    };
+    let mut reached: Vec<Option<Reached>> = vec![None; 4 * (grid.width * rows) as usize];
+    let path = |reached: &[Option<Reached>], mut state: State| {
+        let mut inputs = vec![];
+        while let Some((_, Some((prev, input)))) = index(state).and_then(|i| reached[i]) {
+            inputs.push(input);
+            state = prev;
+        }
+        inputs.reverse();
+        inputs
+    };
+    // by their cells and whether they were spun into
+    let mut found: HashMap<([(i16, i16); 4], bool), Found> = HashMap::new();
+    let mut record = |landed: Figure, cost: usize, from: State, input: Option<Input>| {
+        let spin = input == Some(Input::Rotate);
+        let mut key = landed.blocks.map(|block| (block.x, block.y));
+        key.sort();
+        let entry = found
+            .entry((key, spin))
+            .or_insert((landed, cost, from, input));
+        if cost < entry.1 {
+            *entry = (landed, cost, from, input);
+        }
+    };
+    let start = (0, fig.blocks[0].x, fig.blocks[0].y);
+    let mut queue = VecDeque::from([start]);
+    if let Some(i) = index(start) {
+        reached[i] = Some((0, None));
+    }
+    while let Some(state) = queue.pop_front() {
+        let (turn, x, y) = state;
+        let cost = index(state)
+            .and_then(|i| reached[i])
+            .map_or(0, |(cost, _)| cost);
+        for input in [Input::Left, Input::Right, Input::Rotate, Input::SoftDrop] {
+            let next = match input {
+                Input::Left => (turn, x - 1, y),
+                Input::Right => (turn, x + 1, y),
+                Input::Rotate => ((turn + 1) % 4, x, y),
+                _ => (turn, x, y + grid.fall()),
+            };
+            let moved = figure(next);
+            if !cells.fits(&moved) {
+                continue;
+            }
+            // landing straight down is left for below
+            if cells.landed(&moved) {
+                if input != Input::SoftDrop {
+                    record(moved, cost + 1, state, Some(input));
+                }
+                continue;
+            }
+            if let Some(i) = index(next) {
+                if reached[i].is_none() {
+                    reached[i] = Some((cost + 1, Some((state, input))));
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    // falling takes no input, so where the figure lands going straight down
+    // is reached from the position above it with the fewest inputs. All the
+    // positions between are reached too, as the figure can always move down
+    // from a position where it doesn't lie on anything.
+    for turn in 0..4 {
+        for x in 0..grid.width {
+            let mut best: Option<(usize, State)> = None;
+            let mut y = grid.spawn_row() - 4 * grid.fall();
+            while (-4..grid.height + 4).contains(&y) {
+                let state = (turn, x, y);
+                match index(state).and_then(|i| reached[i]) {
+                    None => best = None,
+                    Some((cost, _)) => {
+                        if best.map_or(true, |(best, _)| cost < best) {
+                            best = Some((cost, state));
+                        }
+                        let below = figure((turn, x, y + grid.fall()));
+                        if let (true, Some((cost, from))) = (cells.landed(&below), best) {
+                            record(below, cost, from, None);
+                        }
+                    }
+                }
+                y += grid.fall();
+            }
+        }
+    }
+    let mut placements: Vec<Placement> = found
+        .into_iter()
+        .map(|((_, spin), (figure, _, from, input))| {
+            let mut inputs = path(&reached, from);
+            inputs.extend(input);
+            Placement {
+                figure,
+                inputs,
+                spin,
+            }
+        })
+        .collect();
+    // the same order every time, so the bots play the same with the same seed
+    placements.sort_by_key(|placement| {
+        let mut cells = placement.figure.blocks.map(|block| (block.x, block.y));
+        cells.sort();
+        (cells, placement.spin)
+    });
+    placements
+}
+
+/// Every place `fig` can lock in on `board`, see `find_placements`.
+pub fn placements(board: &GameState, fig: Figure) -> Vec<Placement> {
+    find_placements(&Cells::new(board), fig)
+}
+
+/// Locks `placement` on `cells`, returns the board left, the lines cleared
+/// and the garbage they send, as if they started a combo.
+fn lock(cells: &Cells, placement: &Placement) -> (Cells, u32, u32) {
+    let fig = &placement.figure;
+    let (tspin, mini) = if placement.spin {
+        tspin_kind(fig, |x, y| cells.is_taken(x, y))
+    } else {
+        (false, false)
+    };
+    let mut after = cells.clone();
+    let lines = after.lock(fig);
+    let perfect = !after.taken.contains(&true);
+    let sent = attack(
+        Clear {
+            lines,
+            tspin,
+            mini,
+            perfect,
+        },
+        0,
+        false,
+    );
+    (after, lines, sent)
+}
+
+/// The best place for `fig` on `cells` and how good it is, as the inputs that
+/// take it there.
+fn best_placement(cells: &Cells, fig: Figure, weights: &Weights) -> Option<(f32, Vec<Input>)> {
+    let mut best: Option<(f32, Vec<Input>)> = None;
+    for placement in find_placements(cells, fig) {
+        let (after, cleared, sent) = lock(cells, &placement);
+        let score = evaluate(&after, cleared, sent, weights);
+        if best.as_ref().map_or(true, |(best, _)| score > *best) {
+            best = Some((score, placement.inputs));
+        }
+    }
+    best
+}
+
+/// The inputs that make `fig` land on the `target` cells of `board`, if it
+/// can get there.
+pub fn inputs_to(board: &GameState, fig: Figure, target: [(i16, i16); 4]) -> Option<Vec<Input>> {
+    let mut target = target;
+    target.sort();
+    placements(board, fig)
+        .into_iter()
+        .filter(|placement| {
+            let mut cells = placement.figure.blocks.map(|block| (block.x, block.y));
+            cells.sort();
+            cells == target
+        })
+        .map(|placement| placement.inputs)
+        .min_by_key(|inputs| inputs.len())
+}
+
+/// Where `fig` lands on `board` if it falls straight from where it is.
+pub fn landing(board: &GameState, fig: Figure) -> Figure {
+    Cells::new(board).drop(fig)
+}
+
+/// The inputs that take the actual figure of `board` to its best place, or
+/// that keep it first when the figure coming instead has a better one.
+fn plan(board: &GameState, fig: Figure, weights: &Weights) -> Vec<Input> {
+    let cells = Cells::new(board);
+    let seat = &board.seats[0];
+    let best = best_placement(&cells, fig, weights);
+    let coming = seat.keep_figure.or(seat.next_figures.first().copied());
+    let held = coming.and_then(|coming| {
+        let (score, mut inputs) =
+            best_placement(&cells, seat.spawned(coming, board.grid), weights)?;
+        inputs.insert(0, Input::Hold);
+        Some((score, inputs))
+    });
+    match (best, held) {
+        (Some((score, _)), Some((held_score, inputs))) if held_score > score => inputs,
+        (None, Some((_, inputs))) => inputs,
+        (best, _) => best.map(|(_, inputs)| inputs).unwrap_or_default(),
+    }
+}
+
+/// How far ahead the bot looks: it places the actual figure and the ones in
+/// the queue in turn, keeping the best boards after each one. The deeper and
+/// the wider, the better it plays and the longer it thinks.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Lookahead {
+    // figures placed in a row, the actual one included
+    pub depth: usize,
+    // boards kept after each figure
+    pub beam: usize,
+    // the most boards the bot places a figure on for each figure, it plays
+    // the best board it got to when they're used up. A count and not a time,
+    // so a seed plays the same on any machine.
+    pub nodes: usize,
+    // the longest the bot thinks about each figure, for a bot playing against
+    // the clock, none to only count the boards
+    pub time: Option<Duration>,
+}
+impl Default for Lookahead {
+    fn default() -> Self {
+        Lookahead {
+            depth: 3,
+            beam: 8,
+            nodes: 12,
+            time: None,
+        }
+    }
+}
+
+/// A board the search got to, with the figures still to place.
+#[derive(Clone, PartialEq, Debug)]
+struct Node {
+    cells: Cells,
+    // the figure to place now, the kept one and the next one in the queue
+    falling: Option<Figure>,
+    kept: Option<Figure>,
+    next: usize,
+    // lines cleared and sent on the way, they count towards the score of
+    // the board
+    cleared: u32,
+    sent: u32,
+    score: f32,
+    // what to press for the actual figure to get here
+    inputs: Vec<Input>,
+}
+impl Node {
+    /// The boards one more figure away, placing the falling figure or keeping
+    /// it for the kept one, or the next one when there is none. `queue` holds
+    /// the figures coming, already spawned.
+    fn children(&self, queue: &[Figure], weights: &Weights, first: bool) -> Vec<Node> {
+        let Some(falling) = self.falling else {
+            return vec![];
+        };
+        let mut choices = vec![(falling, self.kept, self.next, false)];
+        match self.kept {
+            Some(kept) => choices.push((kept, Some(falling), self.next, true)),
+            None => {
+                if let Some(coming) = queue.get(self.next) {
+                    choices.push((*coming, Some(falling), self.next + 1, true));
+                }
+            }
+        }
+        let mut children = vec![];
+        for (fig, kept, next, held) in choices {
+            for placement in find_placements(&self.cells, fig) {
+                let (cells, cleared, sent) = lock(&self.cells, &placement);
+                let (cleared, sent) = (self.cleared + cleared, self.sent + sent);
+                let score = evaluate(&cells, cleared, sent, weights);
+                let inputs = match (first, held) {
+                    (false, _) => self.inputs.clone(),
+                    (true, false) => placement.inputs,
+                    (true, true) => [vec![Input::Hold], placement.inputs].concat(),
+                };
+                children.push(Node {
+                    cells,
+                    falling: queue.get(next).copied(),
+                    kept,
+                    next: next + 1,
+                    cleared,
+                    sent,
+                    score,
+                    inputs,
+                });
+            }
+        }
+        children
+    }
+}
+
+/// The inputs for the actual figure of `board` leading to the best board
+/// `lookahead` finds.
+fn search(board: &GameState, fig: Figure, weights: &Weights, lookahead: &Lookahead) -> Vec<Input> {
+    search_counting(board, fig, weights, lookahead).0
+}
+
+/// `search`, with the count of boards it placed a figure on.
+fn search_counting(
+    board: &GameState,
+    fig: Figure,
+    weights: &Weights,
+    lookahead: &Lookahead,
+) -> (Vec<Input>, usize) {
+    let start = Instant::now();
+    let spent = |expanded: usize| {
+        expanded >= lookahead.nodes || lookahead.time.is_some_and(|time| start.elapsed() > time)
+    };
+    let mut expanded = 0;
+    let seat = &board.seats[0];
+    let spawned = |fig: Figure| seat.spawned(fig, board.grid);
+    let queue: Vec<Figure> = seat.next_figures.iter().copied().map(spawned).collect();
+    let mut beam = vec![Node {
+        cells: Cells::new(board),
+        falling: Some(fig),
+        kept: seat.keep_figure.map(spawned),
+        next: 0,
+        cleared: 0,
+        sent: 0,
+        score: 0.0,
+        inputs: vec![],
+    }];
+    let mut best = None;
+    for depth in 0..lookahead.depth {
+        let mut children = vec![];
+        // the best boards go first, they get searched even when the budget
+        // is short
+        for node in beam.iter() {
+            children.extend(node.children(&queue, weights, depth == 0));
+            expanded += 1;
+            if spent(expanded) {
+                break;
+            }
+        }
+        children.sort_by(|a, b| b.score.total_cmp(&a.score));
+        // different ways to the same board are the same to the figures coming
+        let mut seen = HashSet::new();
+        children.retain(|node| {
+            let kept = node.kept.map(|fig| fig.piece_type.letter());
+            seen.insert((node.cells.taken.clone(), kept, node.next))
+        });
+        children.truncate(lookahead.beam);
+        if children.is_empty() {
+            break;
+        }
+        best = Some(children[0].inputs.clone());
+        beam = children;
+        if spent(expanded) {
+            break;
+        }
+    }
+    (best.unwrap_or_default(), expanded)
+}
+
+// Hard bots look two figures ahead and give up sooner than Expert ones, which
+// use the default lookahead.
+const HARD_LOOKAHEAD: Lookahead = Lookahead {
+    depth: 2,
+    beam: 4,
+    nodes: 4,
+    time: None,
+};
+
+/// How strong a computer opponent is.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Expert,
+}
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Medium,
+        Difficulty::Hard,
+        Difficulty::Expert,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+            Difficulty::Expert => "Expert",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Difficulty> {
+        Difficulty::ALL
+            .into_iter()
+            .find(|difficulty| difficulty.name().eq_ignore_ascii_case(name))
+    }
+
+    /// A bot playing at this level, `seed` picking its mistakes. The levels go
+    /// from about half a piece a second to three, from a wrong place every
+    /// sixth piece to none, and from clearing lines as they come to going for
+    /// the clears sending the most garbage. Hard and Expert bots look ahead
+    /// with a budget of boards.
+    pub fn bot(&self, seed: u64) -> Bot {
+        let (delay, drop, reaction, mistakes, attack, lookahead) = match *self {
+            Difficulty::Easy => (8, 3, 40, 0.15, 0.0, None),
+            Difficulty::Medium => (5, 1, 15, 0.06, 0.2, None),
+            Difficulty::Hard => (2, 0, 6, 0.02, 0.4, Some(HARD_LOOKAHEAD)),
+            Difficulty::Expert => (0, 0, 0, 0.0, 0.6, Some(Lookahead::default())),
+        };
+        let mut bot = Bot::new(delay);
+        bot.drop = drop;
+        bot.reaction = reaction;
+        bot.mistakes = mistakes;
+        bot.weights.attack = attack;
+        bot.lookahead = lookahead;
+        bot.rng = Rand32::new(seed);
+        bot
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Bot {
+    // frames between two inputs, the lower the faster it plays
+    pub delay: u32,
+    // frames between two soft drops, once the figure is in place
+    pub drop: u32,
+    // frames it waits before moving a new figure
+    pub reaction: u32,
+    // chance of putting a figure in a random place instead of the best one
+    pub mistakes: f32,
+    pub weights: Weights,
+    // greedy when there's none, it only looks at the actual and kept figures
+    pub lookahead: Option<Lookahead>,
+    rng: Rand32,
+    wait: u32,
+    // the figure the plan is for, as `Seat::pieces`
+    piece: Option<u64>,
+    plan: VecDeque<Input>,
+}
+impl Bot {
+    pub fn new(delay: u32) -> Self {
+        Bot {
+            delay,
+            drop: delay,
+            reaction: 0,
+            mistakes: 0.0,
+            weights: Weights::default(),
+            lookahead: None,
+            rng: Rand32::new(0),
+            wait: delay,
+            piece: None,
+            plan: VecDeque::new(),
+        }
+    }
+
+    /// What the bot presses on this frame of `board`, once it's done moving
+    /// the figure it keeps dropping it.
+    pub fn input(&mut self, board: &GameState) -> Option<Input> {
+        let seat = &board.seats[0];
+        let fig = seat.actual_figure?;
+        if board.gameover || board.pause {
+            return None;
+        }
+        if self.piece != Some(seat.pieces) {
+            self.piece = Some(seat.pieces);
+            self.wait = self.wait.max(self.reaction);
+            self.plan = if self.mistakes > 0.0 && self.rng.rand_float() < self.mistakes {
+                let mut all = placements(board, fig);
+                if all.is_empty() {
+                    vec![]
+                } else {
+                    let n = self.rng.rand_range(0..all.len() as u32) as usize;
+                    all.swap_remove(n).inputs
+                }
+            } else {
+                match &self.lookahead {
+                    Some(lookahead) => search(board, fig, &self.weights, lookahead),
+                    None => plan(board, fig, &self.weights),
+                }
+            }
+            .into();
+        }
+        if self.wait > 0 {
+            self.wait -= 1;
+            return None;
+        }
+        let input = self.plan.pop_front();
+        self.wait = if self.plan.is_empty() {
+            self.drop
+        } else {
+            self.delay
+        };
+        Some(input.unwrap_or(Input::SoftDrop))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Block;
+    use ggez::graphics::Color;
+
+    // the inputs of the placement covering `cells`
+    fn inputs(placements: &[Placement], mut cells: [(i16, i16); 4]) -> Option<Vec<Input>> {
+        cells.sort();
+        placements
+            .iter()
+            .find(|placement| {
+                let mut taken = placement.figure.blocks.map(|block| (block.x, block.y));
+                taken.sort();
+                taken == cells && !placement.spin
+            })
+            .map(|placement| placement.inputs.clone())
+    }
+
+    #[test]
+    fn parses_weights() {
+        let weights = Weights {
+            lines: 1.5,
+            height: -0.25,
+            holes: -2.0,
+            bumpiness: 0.0,
+            wells: -0.125,
+            attack: 0.75,
+        };
+        assert_eq!(Weights::parse(&weights.to_string()), Ok(weights));
+        let text = "# trained on 20 games\n\n  holes :  -1\nattack: 2\n";
+        assert_eq!(
+            Weights::parse(text),
+            Ok(Weights {
+                holes: -1.0,
+                attack: 2.0,
+                ..Weights::default()
+            })
+        );
+        assert_eq!(Weights::parse(""), Ok(Weights::default()));
+        for (text, error) in [
+            ("lines 1", "line 1: expected `name: value`"),
+            ("holes: -1\nwells: deep", "line 2: `deep` is not a number"),
+            ("\nspeed: 3", "line 2: unknown weight `speed`"),
+        ] {
+            assert_eq!(Weights::parse(text), Err(error.to_string()));
+        }
+    }
+
+    #[test]
+    fn finds_the_fewest_inputs() {
+        let mut board = GameState::with_seed(0);
+        let square = find_placements(&Cells::new(&board), Figure::new(1));
+        assert_eq!(
+            inputs(&square, [(4, 18), (5, 18), (4, 19), (5, 19)]),
+            Some(vec![])
+        );
+        assert_eq!(
+            inputs(&square, [(0, 18), (1, 18), (0, 19), (1, 19)]),
+            Some(vec![Input::Left; 4])
+        );
+        assert_eq!(
+            inputs(&square, [(8, 18), (9, 18), (8, 19), (9, 19)]),
+            Some(vec![Input::Right; 4])
+        );
+        // two full rows but for a T pointing down
+        for x in 0..10 {
+            if !(4..=6).contains(&x) {
+                board.static_blocks.push(Block::new(x, 18, Color::WHITE));
+            }
+            if x != 5 {
+                board.static_blocks.push(Block::new(x, 19, Color::WHITE));
+            }
+        }
+        let t = find_placements(&Cells::new(&board), Figure::new(0));
+        assert_eq!(
+            inputs(&t, [(4, 17), (5, 17), (6, 17), (5, 18)]),
+            None,
+            "the slot is free"
+        );
+        assert_eq!(
+            inputs(&t, [(4, 18), (5, 18), (6, 18), (5, 19)]),
+            Some(vec![Input::Rotate; 2])
+        );
+    }
+
+    #[test]
+    fn lookahead_plays_the_same_every_time() {
+        let play = |nodes: usize| {
+            let mut bot = Bot::new(0);
+            bot.lookahead = Some(Lookahead {
+                nodes,
+                ..Lookahead::default()
+            });
+            let mut board = GameState::with_seed(5);
+            for _ in 0..300 {
+                if let Some(input) = bot.input(&board) {
+                    board.handle_input(input);
+                }
+                board.tick();
+            }
+            (board.stack_cells(), board.seats[0].pieces)
+        };
+        let (cells, pieces) = play(4);
+        assert_eq!(play(4), (cells, pieces));
+        assert!(pieces > 5);
+    }
+
+    #[test]
+    fn search_stops_within_its_budget() {
+        let mut board = GameState::with_seed(9);
+        board.tick();
+        let fig = board.seats[0].actual_figure.unwrap();
+        let weights = Weights::default();
+        for nodes in [1, 2, 5, 12] {
+            let lookahead = Lookahead {
+                nodes,
+                ..Lookahead::default()
+            };
+            let (inputs, expanded) = search_counting(&board, fig, &weights, &lookahead);
+            assert_eq!(expanded, nodes);
+            assert!(!inputs.is_empty());
+        }
+        // the whole search: the first board, then a beam at each next depth
+        let whole = Lookahead {
+            nodes: usize::MAX,
+            ..Lookahead::default()
+        };
+        let (_, expanded) = search_counting(&board, fig, &weights, &whole);
+        assert!(expanded <= 1 + 2 * whole.beam, "{}", expanded);
+        let timed = Lookahead {
+            time: Some(Duration::ZERO),
+            ..whole
+        };
+        assert_eq!(search_counting(&board, fig, &weights, &timed).1, 1);
+    }
+
+    #[test]
+    fn looking_ahead_beats_greedy() {
+        // lines cleared and holes left after the same pieces and garbage,
+        // which the greedy bot leaves holes on
+        let play = |lookahead: Option<Lookahead>| {
+            let mut bot = Bot::new(0);
+            bot.lookahead = lookahead;
+            let mut board = GameState::with_seed(4);
+            while board.seats[0].pieces < 60 && !board.gameover {
+                if board.ticks % 300 == 299 {
+                    board.garbage.receive(2, board.ticks);
+                }
+                if let Some(input) = bot.input(&board) {
+                    board.handle_input(input);
+                }
+                board.tick();
+            }
+            let cells = Cells::new(&board);
+            (board.score, cells.holes(&cells.heights()))
+        };
+        let (greedy_lines, greedy_holes) = play(None);
+        let (lines, holes) = play(Some(Lookahead::default()));
+        assert!(lines > greedy_lines, "{} against {}", lines, greedy_lines);
+        assert!(holes < greedy_holes, "{} against {}", holes, greedy_holes);
+    }
+}
@@ -0,0 +1,410 @@
+//! `tetris --gif <replay>`: draws a stretch of a replay into an animated GIF,
+//! to share a clip of a game. The frames are drawn here pixel by pixel, with
+//! no window and no graphics card: the kept piece on the left, the board and
+//! the queue on the right.
+//!
+//! ```text
+//! tetris --gif <replay> [--from <seconds>] [--to <seconds>] [--out <file>]
+//! ```
+use std::fs::File;
+use std::path::Path;
+
+use ggez::graphics::Color;
+use ggez::{GameError, GameResult};
+
+use crate::replay::{Cursor, Replay};
+use crate::{Figure, GameState, FPS};
+
+// Pixels of a cell of the normal grid, cells of the kept piece and the queue
+// are half as big.
+const CELL: usize = 20;
+const PREVIEW_CELL: usize = CELL / 2;
+// Room for a piece of the queue, in preview cells.
+const PREVIEW_SLOT: usize = 5;
+const MARGIN: usize = CELL;
+// Frames of the game for each frame of the GIF, 20 a second.
+const FRAME_STEP: u64 = 3;
+// How long the last frame stays before the clip starts again, in hundredths
+// of a second.
+const LAST_FRAME_DELAY: u16 = 200;
+const BACKGROUND: Color = Color::BLACK;
+const EMPTY_CELL: Color = Color {
+    r: 0.1,
+    g: 0.1,
+    b: 0.1,
+    a: 1.0,
+};
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct GifOptions {
+    pub replay: String,
+    // the stretch of the replay drawn, in seconds, to its end when there's
+    // no `to`
+    pub from: f32,
+    pub to: Option<f32>,
+    pub out: String,
+}
+impl GifOptions {
+    /// Reads the replay and the options following `--gif`.
+    pub fn parse(args: &[String]) -> GameResult<Self> {
+        let (replay, args) = args.split_first().ok_or_else(|| {
+            GameError::ConfigError("--gif needs the path of a replay file".to_string())
+        })?;
+        let mut options = GifOptions {
+            replay: replay.clone(),
+            from: 0.0,
+            to: None,
+            out: "clip.gif".to_string(),
+        };
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| GameError::ConfigError(format!("{} needs a value", flag)))?;
+            let seconds = || {
+                value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|seconds| *seconds >= 0.0)
+                    .ok_or_else(|| GameError::ConfigError(format!("`{}` is not a time", value)))
+            };
+            match flag.as_str() {
+                "--from" => options.from = seconds()?,
+                "--to" => options.to = Some(seconds()?),
+                "--out" => options.out = value.clone(),
+                _ => {
+                    return Err(GameError::ConfigError(format!(
+                        "unknown option `{}` for --gif",
+                        flag
+                    )))
+                }
+            }
+        }
+        if options.to.is_some_and(|to| to <= options.from) {
+            return Err(GameError::ConfigError(
+                "--to has to come after --from".to_string(),
+            ));
+        }
+        Ok(options)
+    }
+}
+
+/// An image made of palette indices, the palette growing with the colors used.
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+    palette: Vec<(u8, u8, u8)>,
+}
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        let mut canvas = Canvas {
+            width,
+            height,
+            pixels: vec![0; width * height],
+            palette: vec![],
+        };
+        canvas.clear();
+        canvas
+    }
+
+    fn clear(&mut self) {
+        let background = self.index(BACKGROUND);
+        self.pixels.fill(background);
+    }
+
+    fn index(&mut self, color: Color) -> u8 {
+        let rgb = color.to_rgb();
+        match self.palette.iter().position(|known| *known == rgb) {
+            Some(n) => n as u8,
+            // a GIF has 256 colors at most, the clips only use a few
+            None if self.palette.len() < 256 => {
+                // taken before the push, the 256th color is index 255
+                let n = self.palette.len() as u8;
+                self.palette.push(rgb);
+                n
+            }
+            None => 0,
+        }
+    }
+
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: Color) {
+        let index = self.index(color);
+        for row in y.min(self.height)..(y + height).min(self.height) {
+            let start = row * self.width;
+            self.pixels[start + x.min(self.width)..start + (x + width).min(self.width)].fill(index);
+        }
+    }
+
+    /// The square of a cell at `x` and `y` pixels, with a gap to the next one.
+    fn cell(&mut self, x: usize, y: usize, size: usize, color: Color) {
+        self.fill(x, y, size - 1, size - 1, color);
+    }
+
+    fn frame(&self, delay: u16) -> gif::Frame<'static> {
+        let palette: Vec<u8> = self
+            .palette
+            .iter()
+            .flat_map(|(r, g, b)| [*r, *g, *b])
+            .collect();
+        let mut frame = gif::Frame::from_palette_pixels(
+            self.width as u16,
+            self.height as u16,
+            &self.pixels,
+            &palette,
+            None,
+        );
+        frame.delay = delay;
+        frame
+    }
+}
+
+/// `color` shown at `alpha` over the empty cells, in a few steps so the
+/// palette stays small.
+fn faded(color: Color, alpha: f32) -> Color {
+    let alpha = (alpha * 4.0).ceil() / 4.0;
+    let mix = |front: f32, back: f32| front * alpha + back * (1.0 - alpha);
+    Color::new(
+        mix(color.r, EMPTY_CELL.r),
+        mix(color.g, EMPTY_CELL.g),
+        mix(color.b, EMPTY_CELL.b),
+        1.0,
+    )
+}
+
+/// Where things go in the image of a board.
+struct Layout {
+    cell: usize,
+    board_x: usize,
+    queue_x: usize,
+    width: usize,
+    height: usize,
+}
+impl Layout {
+    fn of(state: &GameState) -> Self {
+        let cell = CELL * state.grid.scale as usize;
+        let board_x = MARGIN + PREVIEW_SLOT * PREVIEW_CELL + MARGIN;
+        let queue_x = board_x + state.grid.width as usize * cell + MARGIN;
+        Layout {
+            cell,
+            board_x,
+            queue_x,
+            width: queue_x + PREVIEW_SLOT * PREVIEW_CELL + MARGIN,
+            height: MARGIN + state.grid.height as usize * cell + MARGIN,
+        }
+    }
+}
+
+/// Draws a piece of the queue or the kept one with its top left corner at
+/// `x` and `y`.
+fn draw_preview(canvas: &mut Canvas, fig: &Figure, x: usize, y: usize) {
+    let left = fig.blocks.iter().map(|block| block.x).min().unwrap_or(0);
+    let top = fig.blocks.iter().map(|block| block.y).min().unwrap_or(0);
+    for block in fig.blocks.iter() {
+        canvas.cell(
+            x + (block.x - left) as usize * PREVIEW_CELL,
+            y + (block.y - top) as usize * PREVIEW_CELL,
+            PREVIEW_CELL,
+            block.color,
+        );
+    }
+}
+
+fn draw(canvas: &mut Canvas, state: &GameState, layout: &Layout) {
+    canvas.clear();
+    let grid = state.grid;
+    let cell = layout.cell;
+    let at = |x: i16, y: i16| {
+        (
+            layout.board_x + x as usize * cell,
+            MARGIN + y as usize * cell,
+        )
+    };
+    // figures spawn over the board, only what's on it is drawn
+    let on_board = |x: i16, y: i16| (0..grid.width).contains(&x) && (0..grid.height).contains(&y);
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            let (px, py) = at(x, y);
+            canvas.cell(px, py, cell, EMPTY_CELL);
+        }
+    }
+    for block in state.static_blocks.iter() {
+        let alpha = state.visibility.alpha(block.locked_at, state.ticks);
+        if alpha > 0.0 && on_board(block.x, block.y) {
+            let (px, py) = at(block.x, block.y);
+            canvas.cell(px, py, cell, faded(block.color, alpha));
+        }
+    }
+    let figures = state
+        .seats
+        .iter()
+        .filter_map(|seat| seat.actual_figure.as_ref());
+    for block in figures.flat_map(|fig| fig.blocks.iter()) {
+        if on_board(block.x, block.y) {
+            let (px, py) = at(block.x, block.y);
+            canvas.cell(px, py, cell, block.color);
+        }
+    }
+    let seat = &state.seats[0];
+    if let Some(fig) = &seat.keep_figure {
+        draw_preview(canvas, fig, MARGIN, MARGIN);
+    }
+    let slots = grid.height as usize * cell / (PREVIEW_SLOT * PREVIEW_CELL);
+    for (n, fig) in seat.next_figures.iter().take(slots).enumerate() {
+        let y = MARGIN + n * PREVIEW_SLOT * PREVIEW_CELL;
+        draw_preview(canvas, fig, layout.queue_x, y);
+    }
+}
+
+/// Writes the clip asked for by `options`.
+pub fn export(options: &GifOptions) -> GameResult {
+    let replay = Replay::load(&options.replay)?;
+    let frame_of = |seconds: f32| ((seconds * FPS as f32) as u64).min(replay.frames);
+    let from = frame_of(options.from);
+    let to = options.to.map_or(replay.frames, frame_of);
+    if from >= to {
+        return Err(GameError::ConfigError(format!(
+            "the clip is empty, the replay lasts {:.1} seconds",
+            replay.frames as f32 / FPS as f32
+        )));
+    }
+    let mut cursor = Cursor::new(&replay);
+    while cursor.frame < from {
+        cursor.step(&replay);
+    }
+    let layout = Layout::of(&cursor.state);
+    let mut canvas = Canvas::new(layout.width, layout.height);
+    let gif_error = |e: gif::EncodingError| GameError::CustomError(e.to_string());
+    let file = File::create(Path::new(&options.out))?;
+    let mut encoder = gif::Encoder::new(file, layout.width as u16, layout.height as u16, &[])
+        .map_err(gif_error)?;
+    encoder
+        .set_repeat(gif::Repeat::Infinite)
+        .map_err(gif_error)?;
+    let delay = (FRAME_STEP * 100 / FPS as u64) as u16;
+    let mut frames = 0;
+    loop {
+        draw(&mut canvas, &cursor.state, &layout);
+        let last = cursor.frame + FRAME_STEP >= to;
+        let delay = if last { LAST_FRAME_DELAY } else { delay };
+        encoder
+            .write_frame(&canvas.frame(delay))
+            .map_err(gif_error)?;
+        frames += 1;
+        if last {
+            break;
+        }
+        for _ in 0..FRAME_STEP {
+            cursor.step(&replay);
+        }
+    }
+    println!("{} frames written to {}", frames, options.out);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Block;
+
+    fn args(text: &str) -> Vec<String> {
+        text.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn config_error(result: GameResult<GifOptions>) -> String {
+        match result {
+            Err(GameError::ConfigError(message)) => message,
+            other => panic!("expected a config error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_the_options_of_a_clip() {
+        let options = GifOptions::parse(&args("game.json --from 1.5 --to 4 --out a.gif"));
+        assert_eq!(
+            options.unwrap(),
+            GifOptions {
+                replay: "game.json".to_string(),
+                from: 1.5,
+                to: Some(4.0),
+                out: "a.gif".to_string(),
+            }
+        );
+        let options = GifOptions::parse(&args("game.json")).unwrap();
+        assert_eq!((options.from, options.to), (0.0, None));
+        assert_eq!(options.out, "clip.gif");
+    }
+
+    #[test]
+    fn rejects_clips_it_cant_draw() {
+        assert_eq!(
+            config_error(GifOptions::parse(&[])),
+            "--gif needs the path of a replay file"
+        );
+        assert_eq!(
+            config_error(GifOptions::parse(&args("game.json --from soon"))),
+            "`soon` is not a time"
+        );
+        assert_eq!(
+            config_error(GifOptions::parse(&args("game.json --from -1"))),
+            "`-1` is not a time"
+        );
+        assert_eq!(
+            config_error(GifOptions::parse(&args("game.json --from"))),
+            "--from needs a value"
+        );
+        for stretch in ["--from 3 --to 3", "--from 5 --to 2"] {
+            let options = GifOptions::parse(&args(&format!("game.json {}", stretch)));
+            assert_eq!(config_error(options), "--to has to come after --from");
+        }
+    }
+
+    #[test]
+    fn palettes_hold_256_colors() {
+        let mut canvas = Canvas::new(1, 1);
+        for n in 1..=255 {
+            assert_eq!(canvas.index(Color::from_rgb(n, 0, 0)), n);
+        }
+        assert_eq!(canvas.palette.len(), 256);
+        // no room left, the background is drawn instead
+        assert_eq!(canvas.index(Color::from_rgb(0, 1, 0)), 0);
+        assert_eq!(canvas.index(Color::from_rgb(7, 0, 0)), 7);
+    }
+
+    #[test]
+    fn draws_the_stack_where_its_cells_are() {
+        let mut state = GameState::with_seed(0);
+        state.seats[0].next_figures.clear();
+        let red = Color::from_rgb(200, 0, 0);
+        let blue = Color::from_rgb(0, 0, 200);
+        let bottom = state.grid.height - 1;
+        state.static_blocks = vec![Block::new(0, bottom, red), Block::new(3, bottom - 1, blue)];
+        let layout = Layout::of(&state);
+        let mut canvas = Canvas::new(layout.width, layout.height);
+        draw(&mut canvas, &state, &layout);
+        let pixel = |x: usize, y: usize| {
+            let (r, g, b) = canvas.palette[canvas.pixels[y * canvas.width + x] as usize];
+            Color::from_rgb(r, g, b)
+        };
+        let empty = {
+            let (r, g, b) = EMPTY_CELL.to_rgb();
+            Color::from_rgb(r, g, b)
+        };
+        let corner = |x: i16, y: i16| {
+            (
+                layout.board_x + x as usize * layout.cell,
+                MARGIN + y as usize * layout.cell,
+            )
+        };
+        let (x, y) = corner(0, bottom);
+        assert_eq!(pixel(x, y), red);
+        assert_eq!(pixel(x + layout.cell - 2, y + layout.cell - 2), red);
+        // the gap between two cells
+        assert_eq!(pixel(x + layout.cell - 1, y), BACKGROUND);
+        let (x, y) = corner(3, bottom - 1);
+        assert_eq!(pixel(x + layout.cell / 2, y + layout.cell / 2), blue);
+        let (x, y) = corner(1, bottom);
+        assert_eq!(pixel(x, y), empty);
+        assert_eq!(pixel(0, 0), BACKGROUND);
+    }
+}
@@ -0,0 +1,86 @@
+//! Two players on one machine sharing a board twice as wide, each one with
+//! their own falling figure, queue and kept figure. The figures can't go
+//! through each other, so the players have to make room for one another.
+use ggez::event::{self, Button, GamepadId, KeyCode, KeyMods};
+use ggez::graphics;
+use ggez::{timer, Context, GameResult};
+
+use crate::input::{Input, KeyBindings};
+use crate::settings::Configurable;
+use crate::spectate::Watched;
+use crate::{random_seed, GameState, Layout, FPS};
+
+pub struct Coop {
+    state: GameState,
+    // the keys of the left player and of the right one
+    keys: [KeyBindings; 2],
+    // the first gamepad used after the game starts belongs to the left player,
+    // the next one to the right player
+    gamepads: [Option<GamepadId>; 2],
+}
+impl Coop {
+    pub fn new() -> Self {
+        Coop {
+            state: GameState::coop(random_seed()),
+            keys: [KeyBindings::wasd(), KeyBindings::arrows_right_hand()],
+            gamepads: [None, None],
+        }
+    }
+}
+
+impl Configurable for Coop {}
+
+impl Watched for Coop {
+    fn boards(&self) -> Vec<&GameState> {
+        vec![&self.state]
+    }
+}
+
+impl event::EventHandler<ggez::GameError> for Coop {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        while timer::check_update_time(ctx, FPS) {
+            self.state.tick();
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
+        self.state.draw_board(ctx, &Layout::coop())?;
+        graphics::present(ctx)?;
+        timer::yield_now();
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        _repeat: bool,
+    ) {
+        match keycode {
+            KeyCode::R => self.state.restart(),
+            KeyCode::P => self.state.pause = !self.state.pause,
+            _ => {
+                for (n, keys) in self.keys.iter().enumerate() {
+                    if let Some(input) = keys.input(keycode) {
+                        self.state.handle_seat_input(n, input);
+                    }
+                }
+            }
+        }
+    }
+
+    fn gamepad_button_down_event(&mut self, _ctx: &mut Context, btn: Button, id: GamepadId) {
+        if !self.gamepads.contains(&Some(id)) {
+            if let Some(free) = self.gamepads.iter_mut().find(|pad| pad.is_none()) {
+                *free = Some(id);
+            }
+        }
+        let seat = self.gamepads.iter().position(|pad| *pad == Some(id));
+        if let (Some(n), Some(input)) = (seat, Input::from_button(btn)) {
+            self.state.handle_seat_input(n, input);
+        }
+    }
+}
@@ -0,0 +1,400 @@
+//! An environment for training agents on the game itself, in the style of
+//! Gym: `reset` starts a game from a seed and `step` plays an action, giving
+//! back what the agent sees, the reward and whether the game is over. The
+//! rules are the game's, the actions go through `GameState::handle_input` and
+//! `GameState::tick` like the keys of a player.
+//!
+//! An action is either a raw input played on one frame (`left`, `right`,
+//! `soft_drop`, `rotate`, `hold` or `wait` for none) or a whole placement, one
+//! of the places listed in the observation, which plays the inputs that take
+//! the figure there and drops it until it locks.
+//!
+//! `tetris --env` serves the environment over stdin and stdout, one JSON
+//! object per line each way, for agents written in other languages:
+//!
+//! ```text
+//! > {"command":"reset","seed":42}
+//! < {"board":[[0,0,...],...],"falling":{"piece":"T","cells":[[4,0],...]},"queue":["I",...],"hold":null,"placements":[...]}
+//! > {"command":"step","action":{"type":"place","index":3}}
+//! < {"observation":{...},"reward":0,"attack":0,"done":false}
+//! ```
+//!
+//! The board has the rows from the top, with 0 for an empty cell, 1 to 7 for
+//! the pieces I, O, T, L, J, S and Z and 8 for garbage. Cells are `[x, y]`
+//! with `y` going down from the top row, the figures can be over it.
+use std::io::{self, BufRead, Write};
+
+use ggez::{GameError, GameResult};
+use serde::{Deserialize, Serialize};
+
+use crate::bot::{placements, Placement};
+use crate::input::Input;
+use crate::spectate::cell_letter;
+use crate::{Figure, GameState};
+
+// The pieces in the order of their numbers on the board.
+const PIECES: &str = "IOTLJSZ";
+// A placement that doesn't lock after this many frames of soft drops never
+// will, something else is holding the figure.
+const MAX_DROP_FRAMES: u32 = 1000;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    Left,
+    Right,
+    SoftDrop,
+    Rotate,
+    Hold,
+    // no input this frame
+    Wait,
+    // the placement at `index` in the last observation
+    Place { index: usize },
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Falling {
+    pub piece: char,
+    pub cells: Vec<[i16; 2]>,
+}
+impl Falling {
+    fn of(fig: &Figure) -> Self {
+        Falling {
+            piece: fig.piece_type.letter(),
+            cells: fig.blocks.iter().map(|block| [block.x, block.y]).collect(),
+        }
+    }
+}
+
+/// A place the falling figure, or the one coming instead when it's kept, can
+/// lock in.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Choice {
+    pub hold: bool,
+    pub piece: char,
+    pub cells: Vec<[i16; 2]>,
+    // the figure turned into place last, as a T-spin needs
+    pub spin: bool,
+    // the inputs playing it, as the letters of the network protocol
+    pub inputs: String,
+}
+
+/// What the agent sees.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Observation {
+    pub board: Vec<Vec<u8>>,
+    pub falling: Option<Falling>,
+    pub queue: Vec<char>,
+    pub hold: Option<char>,
+    pub placements: Vec<Choice>,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Step {
+    pub observation: Observation,
+    // lines cleared by the action
+    pub reward: u32,
+    // lines of garbage the clears would send in versus
+    pub attack: u32,
+    pub done: bool,
+}
+
+pub struct Env {
+    state: GameState,
+    // the placements of the last observation, with the inputs playing them
+    placements: Vec<Vec<Input>>,
+}
+impl Env {
+    pub fn new() -> Self {
+        Env {
+            state: GameState::with_seed(0),
+            placements: vec![],
+        }
+    }
+
+    /// Starts a new game, the same for the same seed.
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.state = GameState::with_seed(seed);
+        // the first figure comes on the first frame
+        self.state.tick();
+        self.observe()
+    }
+
+    pub fn step(&mut self, action: Action) -> Result<Step, String> {
+        if self.state.gameover {
+            return Err("the game is over, reset it".to_string());
+        }
+        let score = self.state.score;
+        let attack = self.state.garbage.outgoing;
+        match action {
+            Action::Place { index } => {
+                let inputs = self.placements.get(index).cloned().ok_or(format!(
+                    "there are only {} placements",
+                    self.placements.len()
+                ))?;
+                // all the inputs on one frame, the figure only locks when
+                // the frame ends
+                for input in inputs {
+                    self.state.handle_input(input);
+                }
+                let pieces = self.state.seats[0].pieces;
+                self.state.tick();
+                let mut frames = 0;
+                while self.state.seats[0].pieces == pieces && !self.state.gameover {
+                    if frames == MAX_DROP_FRAMES {
+                        return Err("the figure doesn't lock".to_string());
+                    }
+                    self.state.handle_input(Input::SoftDrop);
+                    self.state.tick();
+                    frames += 1;
+                }
+            }
+            Action::Wait => self.state.tick(),
+            action => {
+                let input = match action {
+                    Action::Left => Input::Left,
+                    Action::Right => Input::Right,
+                    Action::SoftDrop => Input::SoftDrop,
+                    Action::Rotate => Input::Rotate,
+                    _ => Input::Hold,
+                };
+                self.state.handle_input(input);
+                self.state.tick();
+            }
+        }
+        Ok(Step {
+            observation: self.observe(),
+            reward: self.state.score - score,
+            attack: self.state.garbage.outgoing - attack,
+            done: self.state.gameover,
+        })
+    }
+
+    fn observe(&mut self) -> Observation {
+        let state = &self.state;
+        let grid = state.grid;
+        let mut board = vec![vec![0; grid.width as usize]; grid.height as usize];
+        for block in state.static_blocks.iter() {
+            if let Some(cell) = board
+                .get_mut(block.y as usize)
+                .and_then(|row| row.get_mut(block.x as usize))
+            {
+                *cell = PIECES
+                    .find(cell_letter(block.color))
+                    .map_or(8, |n| n as u8 + 1);
+            }
+        }
+        let seat = &state.seats[0];
+        let mut choices = vec![];
+        self.placements.clear();
+        if let (Some(fig), false) = (seat.actual_figure, state.gameover) {
+            let coming = seat.keep_figure.or(seat.next_figures.first().copied());
+            let held = coming.map(|coming| seat.spawned(coming, grid));
+            for (hold, fig) in [(false, Some(fig)), (true, held)] {
+                for placement in fig.map(|fig| placements(state, fig)).unwrap_or_default() {
+                    let Placement {
+                        figure,
+                        mut inputs,
+                        spin,
+                    } = placement;
+                    if hold {
+                        inputs.insert(0, Input::Hold);
+                    }
+                    choices.push(Choice {
+                        hold,
+                        piece: figure.piece_type.letter(),
+                        cells: Falling::of(&figure).cells,
+                        spin,
+                        inputs: inputs.iter().map(Input::letter).collect(),
+                    });
+                    self.placements.push(inputs);
+                }
+            }
+        }
+        Observation {
+            board,
+            falling: seat.actual_figure.as_ref().map(Falling::of),
+            queue: seat
+                .next_figures
+                .iter()
+                .map(|fig| fig.piece_type.letter())
+                .collect(),
+            hold: seat.keep_figure.map(|fig| fig.piece_type.letter()),
+            placements: choices,
+        }
+    }
+}
+
+#[derive(Deserialize, PartialEq, Eq, Debug)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Command {
+    Reset { seed: u64 },
+    Step { action: Action },
+}
+
+/// Serves an environment over stdin and stdout until stdin closes.
+pub fn serve() -> GameResult {
+    let mut env = Env::new();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str(&line) {
+            Ok(Command::Reset { seed }) => serde_json::to_value(env.reset(seed)),
+            Ok(Command::Step { action }) => match env.step(action) {
+                Ok(step) => serde_json::to_value(step),
+                Err(error) => Ok(serde_json::json!({ "error": error })),
+            },
+            Err(error) => Ok(serde_json::json!({ "error": error.to_string() })),
+        }
+        .map_err(|e| GameError::CustomError(e.to_string()))?;
+        writeln!(out, "{}", reply)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Block;
+    use ggez::graphics::Color;
+
+    #[test]
+    fn resets_to_the_same_game_for_a_seed() {
+        let mut env = Env::new();
+        let first = env.reset(7);
+        env.step(Action::Place { index: 0 }).unwrap();
+        assert_eq!(env.reset(7), first);
+        assert!(first.falling.is_some());
+        assert_eq!(first.queue.len(), env.state.seats[0].next_figures.len());
+        assert!(!first.placements.is_empty());
+    }
+
+    #[test]
+    fn places_one_piece_and_counts_its_clears() {
+        let mut env = Env::new();
+        let observation = env.reset(3);
+        let index = observation
+            .placements
+            .iter()
+            .position(|choice| !choice.hold)
+            .unwrap();
+        // the rows of the placement full but for its cells
+        let cells = observation.placements[index].cells.clone();
+        let mut rows: Vec<i16> = cells.iter().map(|cell| cell[1]).collect();
+        rows.sort();
+        rows.dedup();
+        for y in rows.iter().copied() {
+            for x in 0..env.state.grid.width {
+                if !cells.contains(&[x, y]) {
+                    env.state.static_blocks.push(Block::new(x, y, Color::WHITE));
+                }
+            }
+        }
+        env.state.stack_changed();
+        let index = env
+            .observe()
+            .placements
+            .iter()
+            .position(|choice| !choice.hold && choice.cells == cells)
+            .unwrap();
+        let pieces = env.state.seats[0].pieces;
+        let outgoing = env.state.garbage.outgoing;
+        let step = env.step(Action::Place { index }).unwrap();
+        assert_eq!(env.state.seats[0].pieces, pieces + 1);
+        assert_eq!(step.reward, rows.len() as u32);
+        assert_eq!(step.attack, env.state.garbage.outgoing - outgoing);
+        assert!(env.state.static_blocks.is_empty());
+        assert!(step
+            .observation
+            .board
+            .iter()
+            .flatten()
+            .all(|cell| *cell == 0));
+        assert!(!step.done);
+    }
+
+    #[test]
+    fn rejects_placements_not_listed() {
+        let mut env = Env::new();
+        let count = env.reset(1).placements.len();
+        assert_eq!(
+            env.step(Action::Place { index: count }),
+            Err(format!("there are only {} placements", count))
+        );
+    }
+
+    #[test]
+    fn holds_to_place_the_coming_piece() {
+        let mut env = Env::new();
+        let observation = env.reset(5);
+        let falling = observation.falling.unwrap().piece;
+        let coming = observation.queue[0];
+        let index = observation
+            .placements
+            .iter()
+            .position(|choice| choice.hold)
+            .unwrap();
+        let choice = observation.placements[index].clone();
+        assert_eq!(choice.piece, coming);
+        assert!(choice.inputs.starts_with('H'));
+        let step = env.step(Action::Place { index }).unwrap();
+        assert_eq!(step.observation.hold, Some(falling));
+        let number = PIECES.find(coming).unwrap() as u8 + 1;
+        for [x, y] in choice.cells {
+            assert_eq!(step.observation.board[y as usize][x as usize], number);
+        }
+    }
+
+    #[test]
+    fn refuses_to_step_once_done() {
+        let mut env = Env::new();
+        env.reset(2);
+        let mut done = false;
+        for _ in 0..200 {
+            done = env.step(Action::Place { index: 0 }).unwrap().done;
+            if done {
+                break;
+            }
+        }
+        assert!(done);
+        assert_eq!(
+            env.step(Action::Wait),
+            Err("the game is over, reset it".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_commands() {
+        let command: Command =
+            serde_json::from_str(r#"{"command":"step","action":{"type":"place","index":3}}"#)
+                .unwrap();
+        assert_eq!(
+            command,
+            Command::Step {
+                action: Action::Place { index: 3 }
+            }
+        );
+        let command: Command =
+            serde_json::from_str(r#"{"command":"step","action":{"type":"soft_drop"}}"#).unwrap();
+        assert_eq!(
+            command,
+            Command::Step {
+                action: Action::SoftDrop
+            }
+        );
+        let command: Command = serde_json::from_str(r#"{"command":"reset","seed":42}"#).unwrap();
+        assert_eq!(command, Command::Reset { seed: 42 });
+        for line in [
+            r#"{"command":"jump"}"#,
+            r#"{"command":"step","action":{"type":"place"}}"#,
+            r#"{"command":"reset"}"#,
+        ] {
+            assert!(serde_json::from_str::<Command>(line).is_err(), "{}", line);
+        }
+    }
+}
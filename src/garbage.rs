@@ -0,0 +1,271 @@
+//! The attack side of a board in versus: how many lines of garbage each clear
+//! sends, and the garbage received that is waiting to come up.
+use crate::FPS;
+
+// Frames received garbage waits before it can come up, giving the player some
+// time to cancel it.
+const GARBAGE_DELAY: u64 = FPS as u64;
+// Extra lines for every clear in a row, the last value repeats for longer combos.
+const COMBO_TABLE: [u32; 12] = [0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+const BACK_TO_BACK_BONUS: u32 = 1;
+const PERFECT_CLEAR_BONUS: u32 = 10;
+
+/// What a piece did when it locked.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Clear {
+    pub lines: u32,
+    pub tspin: bool,
+    // the T-spin was a mini one, with only one corner taken in front of the T
+    pub mini: bool,
+    // the clear left the board empty
+    pub perfect: bool,
+}
+impl Clear {
+    /// Tetrises and T-spins keep the back-to-back bonus going, any other clear
+    /// ends it.
+    pub fn is_difficult(&self) -> bool {
+        self.lines == 4 || (self.tspin && self.lines > 0)
+    }
+}
+
+/// Lines sent for `clear`, being the `combo`th clear in a row (0 for the first)
+/// and coming right after another difficult clear when `back_to_back` is set.
+pub fn attack(clear: Clear, combo: u32, back_to_back: bool) -> u32 {
+    if clear.lines == 0 {
+        return 0;
+    }
+    let base = match (clear.tspin, clear.mini, clear.lines) {
+        (true, true, 1) => 0,
+        (true, true, _) => 1,
+        (true, false, 1) => 2,
+        (true, false, 2) => 4,
+        (true, false, _) => 6,
+        (false, _, 1) => 0,
+        (false, _, 2) => 1,
+        (false, _, 3) => 2,
+        (false, _, _) => 4,
+    };
+    let combo = COMBO_TABLE[(combo as usize).min(COMBO_TABLE.len() - 1)];
+    let back_to_back = if back_to_back && clear.is_difficult() {
+        BACK_TO_BACK_BONUS
+    } else {
+        0
+    };
+    let perfect = if clear.perfect {
+        PERFECT_CLEAR_BONUS
+    } else {
+        0
+    };
+    base + combo + back_to_back + perfect
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Incoming {
+    lines: u32,
+    // frame of the receiving board from which it can come up
+    ready_at: u64,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Garbage {
+    // clears in a row, `None` once a piece locks without clearing
+    combo: Option<u32>,
+    back_to_back: bool,
+    incoming: Vec<Incoming>,
+    // lines to send to the opponent, already cancelled against `incoming`
+    pub outgoing: u32,
+}
+impl Garbage {
+    pub fn new() -> Self {
+        Garbage {
+            combo: None,
+            back_to_back: false,
+            incoming: vec![],
+            outgoing: 0,
+        }
+    }
+
+    /// Called every time a piece locks. The attack first cancels the garbage
+    /// waiting to come up and whatever is left goes to `outgoing`.
+    pub fn lock(&mut self, clear: Clear) {
+        if clear.lines == 0 {
+            self.combo = None;
+            return;
+        }
+        let combo = self.combo.map_or(0, |combo| combo + 1);
+        let mut lines = attack(clear, combo, self.back_to_back);
+        self.combo = Some(combo);
+        self.back_to_back = clear.is_difficult();
+        while lines > 0 && !self.incoming.is_empty() {
+            let cancelled = lines.min(self.incoming[0].lines);
+            self.incoming[0].lines -= cancelled;
+            lines -= cancelled;
+            if self.incoming[0].lines == 0 {
+                self.incoming.remove(0);
+            }
+        }
+        self.outgoing += lines;
+    }
+
+    /// Queues garbage from the opponent, `now` being the frame of this board.
+    pub fn receive(&mut self, lines: u32, now: u64) {
+        if lines > 0 {
+            self.incoming.push(Incoming {
+                lines,
+                ready_at: now + GARBAGE_DELAY,
+            });
+        }
+    }
+
+    /// Takes out the attacks that have waited long enough, as the number of
+    /// lines of each one.
+    pub fn take_ready(&mut self, now: u64) -> Vec<u32> {
+        let ready = self
+            .incoming
+            .iter()
+            .take_while(|incoming| incoming.ready_at <= now)
+            .count();
+        self.incoming
+            .drain(..ready)
+            .map(|incoming| incoming.lines)
+            .collect()
+    }
+
+    /// Lines waiting to come up, for the meter.
+    pub fn pending(&self) -> u32 {
+        self.incoming.iter().map(|incoming| incoming.lines).sum()
+    }
+
+    /// Clears in a row so far, 0 when there's no combo going.
+    pub fn combo(&self) -> u32 {
+        self.combo.unwrap_or(0)
+    }
+
+    pub fn back_to_back(&self) -> bool {
+        self.back_to_back
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clear(lines: u32) -> Clear {
+        Clear {
+            lines,
+            tspin: false,
+            mini: false,
+            perfect: false,
+        }
+    }
+
+    fn tspin(lines: u32, mini: bool) -> Clear {
+        Clear {
+            tspin: true,
+            mini,
+            ..clear(lines)
+        }
+    }
+
+    #[test]
+    fn attack_table() {
+        let table = [
+            (clear(0), 0),
+            (clear(1), 0),
+            (clear(2), 1),
+            (clear(3), 2),
+            (clear(4), 4),
+            (tspin(0, false), 0),
+            (tspin(1, true), 0),
+            (tspin(2, true), 1),
+            (tspin(1, false), 2),
+            (tspin(2, false), 4),
+            (tspin(3, false), 6),
+        ];
+        for (clear, lines) in table {
+            assert_eq!(attack(clear, 0, false), lines, "{:?}", clear);
+        }
+    }
+
+    #[test]
+    fn combos_follow_the_table() {
+        for combo in 0..COMBO_TABLE.len() as u32 {
+            assert_eq!(attack(clear(1), combo, false), COMBO_TABLE[combo as usize]);
+            assert_eq!(
+                attack(clear(2), combo, false),
+                1 + COMBO_TABLE[combo as usize]
+            );
+        }
+        // the last step repeats
+        assert_eq!(attack(clear(1), 30, false), 5);
+        assert_eq!(attack(clear(0), 5, false), 0);
+    }
+
+    #[test]
+    fn back_to_back_and_perfect_clears() {
+        assert_eq!(attack(clear(4), 0, true), 5);
+        assert_eq!(attack(tspin(2, false), 0, true), 5);
+        assert_eq!(attack(tspin(1, true), 0, true), 1);
+        // easy clears get no bonus
+        assert_eq!(attack(clear(3), 0, true), 2);
+        let perfect = Clear {
+            perfect: true,
+            ..clear(2)
+        };
+        assert_eq!(attack(perfect, 0, false), 11);
+        assert_eq!(attack(perfect, 2, false), 12);
+    }
+
+    #[test]
+    fn lock_counts_combos_and_back_to_back() {
+        let mut garbage = Garbage::new();
+        garbage.lock(clear(4));
+        assert_eq!(garbage.outgoing, 4);
+        assert!(garbage.back_to_back());
+        garbage.lock(clear(4));
+        assert_eq!(garbage.outgoing, 4 + 4 + 1);
+        assert_eq!(garbage.combo(), 1);
+        garbage.lock(clear(1));
+        assert_eq!(garbage.outgoing, 9 + COMBO_TABLE[2]);
+        assert!(!garbage.back_to_back());
+        garbage.lock(clear(0));
+        assert_eq!(garbage.combo(), 0);
+        garbage.lock(clear(2));
+        assert_eq!(garbage.outgoing, 10 + 1);
+    }
+
+    #[test]
+    fn attacks_cancel_incoming_garbage() {
+        let mut garbage = Garbage::new();
+        garbage.receive(3, 0);
+        garbage.receive(2, 0);
+        assert_eq!(garbage.pending(), 5);
+        // a double sends 1, taken from the first attack
+        garbage.lock(clear(2));
+        assert_eq!(garbage.pending(), 4);
+        assert_eq!(garbage.outgoing, 0);
+        // a tetris cancels what's left, the next one goes out
+        garbage.lock(clear(0));
+        garbage.lock(clear(4));
+        assert_eq!(garbage.pending(), 0);
+        assert_eq!(garbage.outgoing, 0);
+        garbage.lock(clear(0));
+        garbage.lock(clear(4));
+        assert_eq!(garbage.outgoing, 4 + BACK_TO_BACK_BONUS);
+        assert!(garbage.take_ready(GARBAGE_DELAY).is_empty());
+    }
+
+    #[test]
+    fn garbage_waits_before_coming_up() {
+        let mut garbage = Garbage::new();
+        garbage.receive(2, 0);
+        garbage.receive(0, 5);
+        garbage.receive(3, 10);
+        assert!(garbage.take_ready(GARBAGE_DELAY - 1).is_empty());
+        assert_eq!(garbage.take_ready(GARBAGE_DELAY), vec![2]);
+        assert_eq!(garbage.pending(), 3);
+        assert_eq!(garbage.take_ready(GARBAGE_DELAY + 9), Vec::<u32>::new());
+        assert_eq!(garbage.take_ready(GARBAGE_DELAY + 10), vec![3]);
+        assert_eq!(garbage.pending(), 0);
+    }
+}
@@ -0,0 +1,307 @@
+//! The best single player games of each mode, kept in `highscores.json` in the
+//! user data directory. A game that makes the top ten of its mode asks for the
+//! player's name on the game over screen before it's written to the table.
+//!
+//! The score of a game is the lines it cleared, so the entries only keep the
+//! lines, with the level the game reached.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ggez::graphics::{self, DrawParam, Font, PxScale, Text};
+use ggez::{filesystem, Context, GameError, GameResult};
+use mint::Point2;
+use serde::{Deserialize, Serialize};
+
+use crate::{Grid, FPS};
+
+const FILE_NAME: &str = "highscores.json";
+const TABLE_SIZE: usize = 10;
+const NAME_LENGTH: usize = 12;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Entry {
+    pub name: String,
+    pub lines: u32,
+    pub level: u32,
+    // seconds played, without the pauses
+    pub time: f32,
+    // when the game was played, in seconds since 1970
+    pub date: u64,
+    pub seed: u64,
+}
+
+/// The top ten of every mode, best first.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct HighScores {
+    pub modes: BTreeMap<String, Vec<Entry>>,
+}
+impl HighScores {
+    /// Reads the tables at `path`, empty when there's no file yet.
+    pub fn load(path: &Path) -> GameResult<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| GameError::ResourceLoadError(format!("{}: {}", path.display(), e))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HighScores::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> GameResult {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| GameError::CustomError(e.to_string()))?;
+        Ok(fs::write(path, json)?)
+    }
+
+    pub fn table(&self, mode: &str) -> &[Entry] {
+        self.modes.get(mode).map_or(&[], Vec::as_slice)
+    }
+
+    /// A game clearing `lines` would make the table of `mode`. Ties go after
+    /// the games that got there first.
+    pub fn qualifies(&self, mode: &str, lines: u32) -> bool {
+        let table = self.table(mode);
+        lines > 0 && (table.len() < TABLE_SIZE || table.iter().any(|entry| entry.lines < lines))
+    }
+
+    /// Puts `entry` in the table of `mode` and gives back its row.
+    pub fn insert(&mut self, mode: &str, entry: Entry) -> usize {
+        let table = self.modes.entry(mode.to_string()).or_default();
+        let row = table
+            .iter()
+            .position(|other| other.lines < entry.lines)
+            .unwrap_or(table.len());
+        table.insert(row, entry);
+        table.truncate(TABLE_SIZE);
+        row
+    }
+}
+
+/// The mode of a single player game, from the modifiers of its grid.
+pub fn mode_name(grid: Grid) -> String {
+    let modifiers: Vec<&str> = [
+        (grid.is_big(), "big"),
+        (grid.mirrored, "mirrored"),
+        (grid.upside_down, "upside down"),
+    ]
+    .iter()
+    .filter(|(on, _)| *on)
+    .map(|(_, name)| *name)
+    .collect();
+    if modifiers.is_empty() {
+        "normal".to_string()
+    } else {
+        modifiers.join(" ")
+    }
+}
+
+/// `seconds` since 1970 as a year, month and day.
+fn date(seconds: u64) -> String {
+    // from the days since 1970 to the civil calendar, counting years from
+    // March so the leap day is last
+    let days = (seconds / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!("{}-{:02}-{:02}", year, month, day)
+}
+
+/// The high scores as the single player game sees them: loaded when the first
+/// game is over, and the name being typed when a game makes the table.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Scoreboard {
+    scores: Option<(PathBuf, HighScores)>,
+    // the game that's over, waiting for a name
+    pending: Option<Entry>,
+    // the mode of the table shown and the row of the last game in it
+    shown: Option<(String, Option<usize>)>,
+    // the name typed last, offered for the next game
+    name: String,
+    // the bot played some of the game, it doesn't go in the tables
+    assisted: bool,
+}
+impl Scoreboard {
+    /// Forgets the game that was over, for a new one.
+    pub fn reset(&mut self) {
+        self.pending = None;
+        self.shown = None;
+        self.assisted = false;
+    }
+
+    /// Keeps the game being played out of the tables.
+    pub fn assist(&mut self) {
+        self.assisted = true;
+    }
+
+    pub fn entering_name(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Looks for the game that's just over, with the lines, level and frames
+    /// it `played`, in the table of its mode, asking for a name when it makes
+    /// it. Only the first call for a game does anything.
+    pub fn game_over(&mut self, ctx: &Context, grid: Grid, played: (u32, u32, u64), seed: u64) {
+        if self.shown.is_some() {
+            return;
+        }
+        if self.scores.is_none() {
+            let path = filesystem::user_data_dir(ctx).join(FILE_NAME);
+            match HighScores::load(&path) {
+                Ok(scores) => self.scores = Some((path, scores)),
+                Err(e) => eprintln!("could not read the high scores: {}", e),
+            }
+        }
+        let mode = mode_name(grid);
+        let (lines, level, ticks) = played;
+        if let Some((_, scores)) = &self.scores {
+            if !self.assisted && scores.qualifies(&mode, lines) {
+                self.pending = Some(Entry {
+                    name: self.name.clone(),
+                    lines,
+                    level,
+                    time: ticks as f32 / FPS as f32,
+                    date: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |time| time.as_secs()),
+                    seed,
+                });
+            }
+        }
+        self.shown = Some((mode, None));
+    }
+
+    /// Types a letter of the name.
+    pub fn type_char(&mut self, character: char) {
+        if let Some(entry) = &mut self.pending {
+            if (character.is_alphanumeric() || character == ' ')
+                && entry.name.chars().count() < NAME_LENGTH
+            {
+                entry.name.push(character);
+            }
+        }
+    }
+
+    pub fn erase_char(&mut self) {
+        if let Some(entry) = &mut self.pending {
+            entry.name.pop();
+        }
+    }
+
+    /// Writes the game that's over to its table with the name typed.
+    pub fn confirm_name(&mut self) {
+        let (Some(mut entry), Some((mode, row))) = (self.pending.take(), &mut self.shown) else {
+            return;
+        };
+        let Some((path, scores)) = &mut self.scores else {
+            return;
+        };
+        entry.name = entry.name.trim().to_string();
+        if entry.name.is_empty() {
+            entry.name = "anonymous".to_string();
+        }
+        self.name = entry.name.clone();
+        *row = Some(scores.insert(mode, entry));
+        if let Err(e) = scores.save(path) {
+            eprintln!("could not save the high scores: {}", e);
+        }
+    }
+
+    /// Draws the name being typed, or the table of the mode of the game that's
+    /// over, from `dest` down.
+    pub fn draw(&self, ctx: &mut Context, dest: Point2<f32>) -> GameResult {
+        let (Some((mode, row)), Some((_, scores))) = (&self.shown, &self.scores) else {
+            return Ok(());
+        };
+        let mut string = match &self.pending {
+            Some(entry) => format!(
+                "New high score in {} mode !\nType your name and press Enter : {}_\n\n",
+                mode, entry.name
+            ),
+            None => format!("High scores, {} mode\n\n", mode),
+        };
+        for (n, entry) in scores.table(mode).iter().enumerate() {
+            string.push_str(&format!(
+                "{}{:>2}. {:<12} {:>5} lines  level {:>2} {:>6.1} s  {}\n",
+                if Some(n) == *row { "> " } else { "  " },
+                n + 1,
+                entry.name,
+                entry.lines,
+                entry.level,
+                entry.time,
+                date(entry.date)
+            ));
+        }
+        let mut text = Text::new(string);
+        text.set_font(Font::default(), PxScale::from(32.0));
+        graphics::draw(ctx, &text, DrawParam::default().dest(dest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, lines: u32) -> Entry {
+        Entry {
+            name: name.to_string(),
+            lines,
+            level: 1 + lines / 10,
+            time: 60.0,
+            date: 0,
+            seed: 0,
+        }
+    }
+
+    fn names(scores: &HighScores) -> Vec<&str> {
+        scores
+            .table("normal")
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn ties_go_after_the_games_already_there() {
+        let mut scores = HighScores::default();
+        assert!(!scores.qualifies("normal", 0));
+        assert!(scores.qualifies("normal", 1));
+        assert_eq!(scores.insert("normal", entry("first", 10)), 0);
+        assert_eq!(scores.insert("normal", entry("second", 10)), 1);
+        assert_eq!(scores.insert("normal", entry("best", 12)), 0);
+        assert_eq!(names(&scores), ["best", "first", "second"]);
+        for n in 0..7 {
+            scores.insert("normal", entry(&format!("low {}", n), 5));
+        }
+        assert_eq!(scores.table("normal").len(), TABLE_SIZE);
+        // a full table only takes games beating its last one
+        assert!(!scores.qualifies("normal", 5));
+        assert!(scores.qualifies("normal", 6));
+        assert!(scores.qualifies("big", 1));
+        assert_eq!(scores.insert("normal", entry("tie", 10)), 3);
+        assert_eq!(scores.table("normal").len(), TABLE_SIZE);
+        assert_eq!(names(&scores)[..4], ["best", "first", "second", "tie"]);
+        assert_eq!(names(&scores)[9], "low 5");
+    }
+
+    #[test]
+    fn dates_follow_the_calendar() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(86399), "1970-01-01");
+        assert_eq!(date(86400), "1970-01-02");
+        assert_eq!(date(951_782_400), "2000-02-29");
+        assert_eq!(date(951_868_800), "2000-03-01");
+        assert_eq!(date(1_700_000_000), "2023-11-14");
+        assert_eq!(date(4_107_542_399), "2100-02-28");
+        assert_eq!(date(4_107_542_400), "2100-03-01");
+    }
+}
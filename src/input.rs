@@ -0,0 +1,189 @@
+//! What a player can do with their figure, and the keys and buttons that do it.
+use ggez::event::{Button, KeyCode};
+
+use crate::settings::Handling;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Input {
+    Left,
+    Right,
+    SoftDrop,
+    Rotate,
+    Hold,
+}
+impl Input {
+    /// The d-pad moves the figure, the bottom face button rotates it and the
+    /// shoulders keep it, on any gamepad.
+    pub fn from_button(btn: Button) -> Option<Input> {
+        match btn {
+            Button::DPadLeft => Some(Input::Left),
+            Button::DPadRight => Some(Input::Right),
+            Button::DPadDown => Some(Input::SoftDrop),
+            Button::DPadUp | Button::South => Some(Input::Rotate),
+            Button::LeftTrigger | Button::RightTrigger => Some(Input::Hold),
+            _ => None,
+        }
+    }
+
+    /// The letter that stands for this input in the network protocol.
+    pub fn letter(&self) -> char {
+        match *self {
+            Input::Left => 'L',
+            Input::Right => 'R',
+            Input::SoftDrop => 'D',
+            Input::Rotate => 'U',
+            Input::Hold => 'H',
+        }
+    }
+
+    pub fn from_letter(letter: char) -> Option<Input> {
+        match letter {
+            'L' => Some(Input::Left),
+            'R' => Some(Input::Right),
+            'D' => Some(Input::SoftDrop),
+            'U' => Some(Input::Rotate),
+            'H' => Some(Input::Hold),
+            _ => None,
+        }
+    }
+}
+
+/// The keys one player uses.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct KeyBindings {
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub soft_drop: KeyCode,
+    pub rotate: KeyCode,
+    pub hold: KeyCode,
+}
+impl KeyBindings {
+    /// The arrows, with C to keep the figure. These are the single player keys.
+    pub fn arrows() -> Self {
+        KeyBindings {
+            left: KeyCode::Left,
+            right: KeyCode::Right,
+            soft_drop: KeyCode::Down,
+            rotate: KeyCode::Up,
+            hold: KeyCode::C,
+        }
+    }
+
+    /// WASD with Q to keep the figure, for the left player in versus.
+    pub fn wasd() -> Self {
+        KeyBindings {
+            left: KeyCode::A,
+            right: KeyCode::D,
+            soft_drop: KeyCode::S,
+            rotate: KeyCode::W,
+            hold: KeyCode::Q,
+        }
+    }
+
+    /// The arrows with right shift to keep the figure, for the right player in
+    /// versus, whose hand is far from C.
+    pub fn arrows_right_hand() -> Self {
+        KeyBindings {
+            hold: KeyCode::RShift,
+            ..KeyBindings::arrows()
+        }
+    }
+
+    pub fn input(&self, key: KeyCode) -> Option<Input> {
+        if key == self.left {
+            Some(Input::Left)
+        } else if key == self.right {
+            Some(Input::Right)
+        } else if key == self.soft_drop {
+            Some(Input::SoftDrop)
+        } else if key == self.rotate {
+            Some(Input::Rotate)
+        } else if key == self.hold {
+            Some(Input::Hold)
+        } else {
+            None
+        }
+    }
+}
+
+/// The key moving the figure that's held down and for how many frames, it
+/// repeats at the rate of the handling settings, not the system's.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Held(Option<(Input, u32)>);
+impl Held {
+    /// `input` was just pressed and played, the moves keep playing while it's
+    /// held.
+    pub fn press(&mut self, input: Input) {
+        if matches!(input, Input::Left | Input::Right | Input::SoftDrop) {
+            *self = Held(Some((input, 0)));
+        }
+    }
+
+    pub fn release(&mut self, input: Input) {
+        if self.0.is_some_and(|(held, _)| held == input) {
+            *self = Held(None);
+        }
+    }
+
+    /// Counts a frame held, gives the input held and how many times it plays
+    /// again on this frame: first after the delay and then at the rate of
+    /// `handling`, as many times as it takes to cross the grid for no delay.
+    pub fn repeat(&mut self, handling: &Handling, width: i16) -> Option<(Input, i16)> {
+        let (input, frames) = self.0?;
+        let frames = frames + 1;
+        self.0 = Some((input, frames));
+        let Handling {
+            das,
+            arr,
+            soft_drop,
+        } = *handling;
+        let times = match input {
+            Input::SoftDrop => (frames % soft_drop == 0) as i16,
+            _ if frames < das => 0,
+            _ if arr == 0 => width,
+            _ => ((frames - das) % arr == 0) as i16,
+        };
+        Some((input, times))
+    }
+
+    /// The inputs the held key plays on this frame, see `repeat`.
+    pub fn repeats(&mut self, handling: &Handling, width: i16) -> Vec<Input> {
+        self.repeat(handling, width)
+            .map(|(input, times)| vec![input; times as usize])
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn held_keys_repeat_after_the_delay() {
+        let handling = Handling {
+            das: 3,
+            arr: 2,
+            soft_drop: 1,
+        };
+        let mut held = Held::default();
+        held.press(Input::Rotate);
+        assert_eq!(held.repeat(&handling, 10), None);
+        held.press(Input::Left);
+        let times: Vec<i16> = (0..8)
+            .map(|_| held.repeat(&handling, 10).unwrap().1)
+            .collect();
+        assert_eq!(times, [0, 0, 1, 0, 1, 0, 1, 0]);
+        // releasing another key keeps it going
+        held.release(Input::Right);
+        assert_eq!(held.repeats(&handling, 10), [Input::Left]);
+        held.release(Input::Left);
+        assert_eq!(held.repeats(&handling, 10), []);
+        let instant = Handling { arr: 0, ..handling };
+        held.press(Input::Right);
+        held.repeat(&instant, 10);
+        held.repeat(&instant, 10);
+        assert_eq!(held.repeats(&instant, 10), [Input::Right; 10]);
+        held.press(Input::SoftDrop);
+        assert_eq!(held.repeats(&instant, 10), [Input::SoftDrop]);
+    }
+}
@@ -0,0 +1,2422 @@
+#![allow(dead_code)]
+//#![allow(unused_imports)]
+use oorandom::Rand32;
+use std::time::Duration;
+
+// Next we need to actually `use` the pieces of ggez that we are going
+// to need frequently.
+use ggez::event::{KeyCode, KeyMods};
+use ggez::graphics::{Color, DrawParam, Font, PxScale, Text};
+use ggez::{event, graphics, timer, Context, GameResult};
+use mint::Point2;
+use serde::{Deserialize, Serialize};
+
+mod benchmark;
+mod bot;
+mod clip;
+mod coop;
+mod env;
+mod garbage;
+mod highscore;
+mod input;
+mod net;
+mod puzzle;
+mod replay;
+mod rollback;
+mod royale;
+mod settings;
+mod spectate;
+pub mod tbp;
+mod train;
+mod versus;
+use benchmark::BenchmarkOptions;
+use bot::{Bot, Difficulty, Lookahead, Weights};
+use clip::GifOptions;
+use coop::Coop;
+use garbage::{Clear, Garbage};
+use highscore::Scoreboard;
+pub use input::Input;
+use input::{Held, KeyBindings};
+pub use net::PROTOCOL_VERSION;
+use net::{Link, NetVersus, Netcode};
+use puzzle::{Outcome, Puzzle};
+use replay::{Event, Playback, Recorder, Replay};
+use rollback::{LoopbackVersus, RollbackVersus};
+use royale::Royale;
+use settings::{Configurable, Configured, Gameplay, Handling, Settings, SETTINGS_FILE};
+use spectate::{Broadcast, Spectator, Watched};
+use tbp::TbpGame;
+use train::TrainOptions;
+use versus::Versus;
+// Now we define the pixel size of each tile, which we make 48x48 pixels.
+const GRID_CELL_SIZE: i16 = 48;
+// 717.0 for 1080p
+const INIT_GRID: f32 = (SCREEN_SIZE.0 / 3.0)
+    + ((SCREEN_SIZE.0 / 3.0) - (11.0 * 5.0 + 10.0 * ((GRID_CELL_SIZE - 5) as f32))) / 2.0;
+// The playfield is 10 cells wide and 20 cells tall.
+const GRID_WIDTH: i16 = 10;
+const GRID_HEIGHT: i16 = 20;
+// Next we define how large we want our actual window to be by multiplying
+// the components of our grid size by its corresponding pixel size.
+const SCREEN_SIZE: (f32, f32) = (1920_f32, 1080_f32);
+const FPS: u32 = 60;
+// Color of the garbage blocks, the ones that don't come from a piece.
+const GARBAGE_COLOR: Color = Color {
+    r: 0.5,
+    g: 0.5,
+    b: 0.5,
+    a: 1.0,
+};
+// How many frames a locked block takes to fade out in the fading stack mode.
+const FADE_FRAMES: u64 = 5 * FPS as u64;
+// Frames between two inputs of the bot playing for the player.
+const AUTOPLAY_DELAY: u32 = 6;
+// Lines to clear to go up a level.
+const LINES_PER_LEVEL: u32 = 10;
+// Frames a figure takes to fall a row on level 1, each level takes
+// `FALL_STEP` off down to `FASTEST_FALL`.
+const SLOWEST_FALL: u32 = 60;
+const FALL_STEP: u32 = 5;
+const FASTEST_FALL: u32 = 10;
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Block {
+    // column and row of the block, row 0 is the top of the playfield
+    x: i16,
+    y: i16,
+    color: Color,
+    // frame in which the block was locked into the stack
+    locked_at: u64,
+}
+impl Block {
+    /// We make a standard helper function so that we can create a new `GridPosition`
+    /// more easily.
+    pub fn new(x: i16, y: i16, color: Color) -> Self {
+        Block {
+            x,
+            y,
+            color,
+            locked_at: 0,
+        }
+    }
+
+    /// Where the block sits when a figure is drawn at half size, as the queue
+    /// and the kept figure are, from the left edge of the preview.
+    pub fn preview_pos(&self) -> (f32, f32) {
+        let x = self.x * GRID_CELL_SIZE / 2;
+        let y = (GRID_CELL_SIZE + self.y * GRID_CELL_SIZE) / 2;
+        (x as f32, y as f32)
+    }
+}
+/// The logical playfield the rules run on. `scale` is how many drawn cells
+/// each logical cell takes in each axis, so Big mode is a 5x10 grid where
+/// every mino is drawn as 2x2 cells.
+///
+/// A grid can also be mirrored or have its gravity pulling upwards. Pieces
+/// are defined for the standard board, with gravity pulling down, and `orient`
+/// turns them into this one, so the rules should ask the grid where the floor
+/// is instead of assuming it is the last row.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Grid {
+    width: i16,
+    height: i16,
+    scale: i16,
+    mirrored: bool,
+    upside_down: bool,
+}
+impl Grid {
+    pub fn normal() -> Self {
+        Grid {
+            width: GRID_WIDTH,
+            height: GRID_HEIGHT,
+            scale: 1,
+            mirrored: false,
+            upside_down: false,
+        }
+    }
+
+    pub fn big() -> Self {
+        Grid {
+            width: GRID_WIDTH / 2,
+            height: GRID_HEIGHT / 2,
+            scale: 2,
+            mirrored: false,
+            upside_down: false,
+        }
+    }
+
+    /// Twice as wide as the normal grid, for co-op.
+    pub fn wide() -> Self {
+        Grid {
+            width: 2 * GRID_WIDTH,
+            ..Grid::normal()
+        }
+    }
+
+    pub fn is_big(&self) -> bool {
+        self.scale > 1
+    }
+
+    /// The same grid without the mirror and with gravity pulling down.
+    pub fn standard(&self) -> Self {
+        Grid {
+            mirrored: false,
+            upside_down: false,
+            ..*self
+        }
+    }
+
+    /// Moves a block between the standard board and this one. Doing it twice
+    /// gives back the same block.
+    pub fn orient(&self, block: Block) -> Block {
+        Block {
+            x: if self.mirrored {
+                self.width - 1 - block.x
+            } else {
+                block.x
+            },
+            y: if self.upside_down {
+                self.height - 1 - block.y
+            } else {
+                block.y
+            },
+            ..block
+        }
+    }
+
+    /// Orients a block of a figure in the queue or kept, which are drawn on their
+    /// own small standard board, so they look like they will once they spawn.
+    pub fn orient_preview(&self, block: Block) -> Block {
+        Grid {
+            width: GRID_WIDTH,
+            height: 4,
+            ..*self
+        }
+        .orient(block)
+    }
+
+    /// How many rows a piece moves each time it falls.
+    pub fn fall(&self) -> i16 {
+        if self.upside_down {
+            -1
+        } else {
+            1
+        }
+    }
+
+    /// The row pieces land on when there is nothing under them.
+    pub fn floor_row(&self) -> i16 {
+        if self.upside_down {
+            0
+        } else {
+            self.height - 1
+        }
+    }
+
+    /// The row pieces come from, the game is over once the stack gets there.
+    pub fn spawn_row(&self) -> i16 {
+        if self.upside_down {
+            self.height - 1
+        } else {
+            0
+        }
+    }
+
+    /// Whether row `y` is further from the floor than row `row`.
+    pub fn is_above(&self, y: i16, row: i16) -> bool {
+        (row - y) * self.fall() > 0
+    }
+
+    /// Cells outside the walls or under the floor. There is no ceiling so pieces
+    /// can rotate above the spawn row.
+    pub fn ilegal_coords(&self, x: i16, y: i16) -> bool {
+        x < 0 || x >= self.width || self.is_above(self.floor_row(), y)
+    }
+
+    /// Pieces are created centered on a standard board, this is how many columns
+    /// they have to be moved to be centered on this one.
+    pub fn spawn_offset(&self) -> i16 {
+        (self.width - GRID_WIDTH) / 2
+    }
+
+    /// Pixel size of one logical cell.
+    pub fn cell_size(&self) -> i16 {
+        GRID_CELL_SIZE * self.scale
+    }
+
+    /// Rectangle where a block at the given cell gets drawn, for a board whose
+    /// left edge is at `board_x`.
+    pub fn block_rect(&self, block: &Block, board_x: f32) -> graphics::Rect {
+        let cell = self.cell_size() as f32;
+        graphics::Rect::new(
+            board_x + block.x as f32 * cell,
+            GRID_CELL_SIZE as f32 + block.y as f32 * cell,
+            cell - 5.0,
+            cell - 5.0,
+        )
+    }
+}
+/// Where the parts of one board are drawn on the screen.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Layout {
+    // left edge of the playfield
+    board_x: f32,
+    // left edges of the kept figure and the queue, and the top of both
+    keep_x: f32,
+    queue_x: f32,
+    preview_y: f32,
+    // how much further right the previews of each seat are from the last one's
+    seat_spacing: f32,
+    score: Point2<f32>,
+    // the game over message and how wide it can get before wrapping
+    message: Point2<f32>,
+    message_width: f32,
+}
+impl Layout {
+    /// One board in the middle of the screen.
+    pub fn single() -> Self {
+        let board_x = INIT_GRID.floor();
+        let cell = GRID_CELL_SIZE as f32;
+        let preview_x = (board_x as i16 / 2) as f32;
+        let message = Point2 {
+            x: board_x - 4.0 * cell,
+            y: 1.0,
+        };
+        Layout {
+            board_x,
+            keep_x: preview_x - 6.0 * cell,
+            queue_x: preview_x + 22.0 * cell,
+            preview_y: 2.0 * cell,
+            seat_spacing: 0.0,
+            score: Point2 {
+                x: board_x - 10.0 * cell,
+                y: SCREEN_SIZE.1 / 2.0,
+            },
+            message,
+            message_width: SCREEN_SIZE.0 - message.x,
+        }
+    }
+
+    /// One of the halves of the screen in a two player game, 0 is the left one.
+    pub fn versus(player: usize) -> Self {
+        let cell = GRID_CELL_SIZE as f32;
+        let half = SCREEN_SIZE.0 / 2.0;
+        let left = half * player as f32;
+        let board_x = (left + (half - GRID_WIDTH as f32 * cell) / 2.0).floor();
+        Layout {
+            board_x,
+            keep_x: board_x - 4.5 * cell,
+            queue_x: board_x + (GRID_WIDTH as f32 - 1.0) * cell,
+            preview_y: 2.0 * cell,
+            seat_spacing: 0.0,
+            score: Point2 {
+                x: board_x - 4.5 * cell,
+                y: SCREEN_SIZE.1 / 2.0,
+            },
+            message: Point2 {
+                x: left + cell,
+                y: 1.0,
+            },
+            message_width: half - 2.0 * cell,
+        }
+    }
+
+    /// The player's board in the middle of the screen with the rest of it
+    /// left for the opponents, for the battle royale.
+    pub fn royale() -> Self {
+        let cell = GRID_CELL_SIZE as f32;
+        let board_x = ((SCREEN_SIZE.0 - GRID_WIDTH as f32 * cell) / 2.0).floor();
+        Layout {
+            board_x,
+            keep_x: board_x - 4.5 * cell,
+            queue_x: board_x + (GRID_WIDTH as f32 - 1.0) * cell,
+            preview_y: 2.0 * cell,
+            seat_spacing: 0.0,
+            score: Point2 {
+                x: board_x - 4.5 * cell,
+                y: SCREEN_SIZE.1 / 2.0,
+            },
+            message: Point2 { x: board_x, y: 1.0 },
+            message_width: GRID_WIDTH as f32 * cell,
+        }
+    }
+
+    /// The wide co-op board in the middle of the screen, the queue and the
+    /// kept figure of each player on their side of it.
+    pub fn coop() -> Self {
+        let cell = GRID_CELL_SIZE as f32;
+        let board_x = ((SCREEN_SIZE.0 - 2.0 * GRID_WIDTH as f32 * cell) / 2.0).floor();
+        Layout {
+            board_x,
+            keep_x: board_x - 4.5 * cell,
+            queue_x: board_x - 9.0 * cell,
+            preview_y: 2.0 * cell,
+            seat_spacing: 29.0 * cell,
+            score: Point2 {
+                x: board_x - 4.5 * cell,
+                y: SCREEN_SIZE.1 / 2.0,
+            },
+            message: Point2 { x: board_x, y: 1.0 },
+            message_width: 2.0 * GRID_WIDTH as f32 * cell,
+        }
+    }
+}
+/// How the locked blocks are shown. The rules always see every block in
+/// `static_blocks`, this only changes what gets drawn.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum StackVisibility {
+    Visible,
+    Fading,
+    Invisible,
+}
+impl StackVisibility {
+    /// The mode that comes after this one when cycling with the `I` key.
+    pub fn next(&self) -> Self {
+        match *self {
+            StackVisibility::Visible => StackVisibility::Fading,
+            StackVisibility::Fading => StackVisibility::Invisible,
+            StackVisibility::Invisible => StackVisibility::Visible,
+        }
+    }
+
+    /// How opaque a block locked at `locked_at` should be drawn at frame `now`,
+    /// from 0.0 (hidden) to 1.0.
+    pub fn alpha(&self, locked_at: u64, now: u64) -> f32 {
+        match *self {
+            StackVisibility::Visible => 1.0,
+            StackVisibility::Fading => {
+                let age = now.saturating_sub(locked_at) as f32;
+                1.0 - (age / FADE_FRAMES as f32).min(1.0)
+            }
+            StackVisibility::Invisible => 0.0,
+        }
+    }
+}
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+impl Direction {
+    /// We create a helper function that will allow us to easily get the inverse
+    /// of a `Direction` which we can use later to check if the player should be
+    /// able to move the snake in a certain direction.
+    pub fn inverse(&self) -> Self {
+        match *self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+
+    /// We also create a helper function that will let us convert between a
+    /// `ggez` `Keycode` and the `Direction` that it represents. Of course,
+    /// not every keycode represents a direction, so we return `None` if this
+    /// is the case.
+    pub fn from_keycode(key: KeyCode) -> Option<Direction> {
+        match key {
+            KeyCode::Up => Some(Direction::Up),
+            KeyCode::Down => Some(Direction::Down),
+            KeyCode::Left => Some(Direction::Left),
+            KeyCode::Right => Some(Direction::Right),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PieceType {
+    T,
+    Square,
+    Stick,
+    LL,
+    LR,
+    ZL,
+    ZR,
+}
+impl PieceType {
+    /// The number `Figure::new` takes to build a figure of this type.
+    pub fn number(&self) -> u32 {
+        match *self {
+            PieceType::T => 0,
+            PieceType::Square => 1,
+            PieceType::Stick => 2,
+            PieceType::LR => 3,
+            PieceType::LL => 4,
+            PieceType::ZL => 5,
+            PieceType::ZR => 6,
+        }
+    }
+
+    /// The usual one letter name of the piece.
+    pub fn letter(&self) -> char {
+        match *self {
+            PieceType::T => 'T',
+            PieceType::Square => 'O',
+            PieceType::Stick => 'I',
+            PieceType::LR => 'L',
+            PieceType::LL => 'J',
+            PieceType::ZL => 'Z',
+            PieceType::ZR => 'S',
+        }
+    }
+
+    /// Reads the usual one letter names of the pieces (I, O, T, L, J, S and Z).
+    pub fn from_letter(letter: char) -> Option<PieceType> {
+        match letter.to_ascii_uppercase() {
+            'T' => Some(PieceType::T),
+            'O' => Some(PieceType::Square),
+            'I' => Some(PieceType::Stick),
+            'L' => Some(PieceType::LR),
+            'J' => Some(PieceType::LL),
+            'Z' => Some(PieceType::ZL),
+            'S' => Some(PieceType::ZR),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Figure {
+    piece_type: PieceType,
+    rotation: Direction,
+    blocks: [Block; 4],
+}
+impl Figure {
+    /// A figure picked by `rng`, so games with the same seed get the same figures.
+    fn random(rng: &mut Rand32) -> Figure {
+        Figure::new(rng.rand_range(0..7))
+    }
+
+    fn new(number: u32) -> Figure {
+        let blocks: [Block; 4];
+        let color: Color;
+        let piece_type = match number {
+            0 => {
+                color = Color::CYAN;
+                blocks = [
+                    Block::new(5, 1, color),
+                    Block::new(5, 0, color),
+                    Block::new(6, 1, color),
+                    Block::new(4, 1, color),
+                ];
+                PieceType::T
+            }
+            1 => {
+                color = Color::BLUE;
+                blocks = [
+                    Block::new(4, 0, color),
+                    Block::new(4, 1, color),
+                    Block::new(5, 0, color),
+                    Block::new(5, 1, color),
+                ];
+                PieceType::Square
+            }
+            2 => {
+                color = Color::RED;
+                blocks = [
+                    Block::new(4, 2, color),
+                    Block::new(4, 1, color),
+                    Block::new(4, 3, color),
+                    Block::new(4, 0, color),
+                ];
+
+                PieceType::Stick
+            }
+            3 => {
+                color = Color::MAGENTA;
+                blocks = [
+                    Block::new(4, 2, color),
+                    Block::new(4, 0, color),
+                    Block::new(4, 1, color),
+                    Block::new(5, 2, color),
+                ];
+                PieceType::LR
+            }
+            4 => {
+                color = Color::GREEN;
+                blocks = [
+                    Block::new(5, 2, color),
+                    Block::new(5, 0, color),
+                    Block::new(5, 1, color),
+                    Block::new(4, 2, color),
+                ];
+                PieceType::LL
+            }
+            5 => {
+                color = Color::YELLOW;
+                blocks = [
+                    Block::new(5, 1, color),
+                    Block::new(5, 0, color),
+                    Block::new(4, 0, color),
+                    Block::new(6, 1, color),
+                ];
+                PieceType::ZL
+            }
+            6 => {
+                color = Color::from_rgba(255, 128, 0, 255);
+                blocks = [
+                    Block::new(4, 1, color),
+                    Block::new(4, 0, color),
+                    Block::new(5, 0, color),
+                    Block::new(3, 1, color),
+                ];
+                PieceType::ZR
+            }
+            _ => panic!("wtf is this ?"),
+        };
+        Figure {
+            piece_type,
+            rotation: Direction::Up,
+            blocks,
+        }
+    }
+    /// The same figure centered on `grid` instead of on a standard board.
+    fn spawned_on(mut self, grid: Grid) -> Figure {
+        for block in self.blocks.iter_mut() {
+            block.x += grid.spawn_offset();
+        }
+        self.oriented(grid)
+    }
+    /// Moves every block between the standard board and `grid`, see `Grid::orient`.
+    fn oriented(mut self, grid: Grid) -> Figure {
+        for block in self.blocks.iter_mut() {
+            *block = grid.orient(*block);
+        }
+        self
+    }
+    fn some_block_is_in_y(&self, y: i16) -> bool {
+        self.blocks.iter().any(|block| block.y == y)
+    }
+
+    fn legal_move(&self, grid: Grid) -> bool {
+        !self
+            .blocks
+            .iter()
+            .any(|block| grid.ilegal_coords(block.x, block.y))
+    }
+    fn restore_blocks(&mut self, prev_blocks: [Block; 4], dir: Direction) {
+        self.blocks = prev_blocks;
+        self.rotation = dir;
+    }
+    /// Rotates the figure the way it would rotate on the standard board, so on a
+    /// mirrored grid it turns the other way round.
+    fn rotate(&mut self, grid: Grid) {
+        let mut standard = self.oriented(grid);
+        standard.rotate_standard(grid.standard());
+        *self = standard.oriented(grid);
+    }
+    fn rotate_standard(&mut self, grid: Grid) {
+        let center_block = self.blocks[0];
+        let prev_blocks = self.blocks;
+        match self.piece_type {
+            PieceType::T => match self.rotation {
+                Direction::Up => {
+                    self.blocks[1].x = center_block.x;
+                    self.blocks[1].y = center_block.y - 2;
+                    self.blocks[2].x = center_block.x;
+                    self.blocks[2].y = center_block.y - 1;
+                    self.blocks[3].x = center_block.x + 1;
+                    self.blocks[3].y = center_block.y - 1;
+                    self.rotation = Direction::Right;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Up);
+                    }
+                }
+                Direction::Down => {
+                    self.blocks[1].x = center_block.x - 1;
+                    self.blocks[1].y = center_block.y - 1;
+                    self.blocks[2].x = center_block.x;
+                    self.blocks[2].y = center_block.y - 1;
+                    self.blocks[3].x = center_block.x;
+                    self.blocks[3].y = center_block.y - 2;
+                    self.rotation = Direction::Left;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Down);
+                    }
+                }
+                Direction::Left => {
+                    self.blocks[1].x = center_block.x - 1;
+                    self.blocks[1].y = center_block.y;
+                    self.blocks[2].x = center_block.x;
+                    self.blocks[2].y = center_block.y - 1;
+                    self.blocks[3].x = center_block.x + 1;
+                    self.blocks[3].y = center_block.y;
+                    self.rotation = Direction::Up;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Left);
+                    }
+                }
+                Direction::Right => {
+                    self.blocks[1].x = center_block.x - 1;
+                    self.blocks[1].y = center_block.y - 1;
+                    self.blocks[2].x = center_block.x;
+                    self.blocks[2].y = center_block.y - 1;
+                    self.blocks[3].x = center_block.x + 1;
+                    self.blocks[3].y = center_block.y - 1;
+                    self.rotation = Direction::Down;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Right);
+                    }
+                }
+            },
+            PieceType::Square => {}
+            PieceType::Stick => match self.rotation {
+                Direction::Up => {
+                    self.blocks[1].x = center_block.x - 2;
+                    self.blocks[1].y = center_block.y;
+                    self.blocks[2].x = center_block.x - 1;
+                    self.blocks[2].y = center_block.y;
+                    self.blocks[3].x = center_block.x + 1;
+                    self.blocks[3].y = center_block.y;
+                    self.rotation = Direction::Left;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Up);
+                    }
+                }
+                Direction::Down => {
+                    self.blocks[1].x = center_block.x + 2;
+                    self.blocks[1].y = center_block.y;
+                    self.blocks[2].x = center_block.x - 1;
+                    self.blocks[2].y = center_block.y;
+                    self.blocks[3].x = center_block.x + 1;
+                    self.blocks[3].y = center_block.y;
+                    self.rotation = Direction::Right;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Down);
+                    }
+                }
+                Direction::Left => {
+                    self.blocks[1].x = center_block.x;
+                    self.blocks[1].y = center_block.y - 2;
+                    self.blocks[2].x = center_block.x;
+                    self.blocks[2].y = center_block.y - 1;
+                    self.blocks[3].x = center_block.x;
+                    self.blocks[3].y = center_block.y + 1;
+                    self.rotation = Direction::Down;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Left);
+                    }
+                }
+                Direction::Right => {
+                    self.blocks[1].x = center_block.x;
+                    self.blocks[1].y = center_block.y - 2;
+                    self.blocks[2].x = center_block.x;
+                    self.blocks[2].y = center_block.y - 1;
+                    self.blocks[3].x = center_block.x;
+                    self.blocks[3].y = center_block.y + 1;
+                    self.rotation = Direction::Up;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Right);
+                    }
+                }
+            },
+            PieceType::LR => match self.rotation {
+                Direction::Up => {
+                    self.blocks[1].x = center_block.x + 1;
+                    self.blocks[1].y = center_block.y;
+                    self.blocks[2].x = center_block.x - 1;
+                    self.blocks[2].y = center_block.y;
+                    self.blocks[3].x = center_block.x + 1;
+                    self.blocks[3].y = center_block.y - 1;
+                    self.rotation = Direction::Left;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Up);
+                    }
+                }
+                Direction::Down => {
+                    self.blocks[1].x = center_block.x + 1;
+                    self.blocks[1].y = center_block.y;
+                    self.blocks[2].x = center_block.x - 1;
+                    self.blocks[2].y = center_block.y;
+                    self.blocks[3].x = center_block.x - 1;
+                    self.blocks[3].y = center_block.y + 1;
+                    self.rotation = Direction::Right;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Down);
+                    }
+                }
+                Direction::Left => {
+                    self.blocks[0].x = center_block.x;
+                    self.blocks[0].y = center_block.y - 1;
+                    self.blocks[1].x = center_block.x;
+                    self.blocks[1].y = center_block.y;
+                    self.blocks[2].x = center_block.x;
+                    self.blocks[2].y = center_block.y - 2;
+                    self.blocks[3].x = center_block.x - 1;
+                    self.blocks[3].y = center_block.y - 2;
+                    self.rotation = Direction::Down;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Left);
+                    }
+                }
+                Direction::Right => {
+                    self.blocks[0].x = center_block.x;
+                    self.blocks[0].y = center_block.y + 1;
+                    self.blocks[1].x = center_block.x;
+                    self.blocks[1].y = center_block.y;
+                    self.blocks[2].x = center_block.x;
+                    self.blocks[2].y = center_block.y - 1;
+                    self.blocks[3].x = center_block.x + 1;
+                    self.blocks[3].y = center_block.y + 1;
+                    self.rotation = Direction::Up;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Right);
+                    }
+                }
+            },
+            PieceType::LL => match self.rotation {
+                Direction::Up => {
+                    self.blocks[1].x = center_block.x + 1;
+                    self.blocks[1].y = center_block.y;
+                    self.blocks[2].x = center_block.x - 1;
+                    self.blocks[2].y = center_block.y;
+                    self.blocks[3].x = center_block.x - 1;
+                    self.blocks[3].y = center_block.y - 1;
+                    self.rotation = Direction::Left;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Up);
+                    }
+                }
+                Direction::Down => {
+                    self.blocks[1].x = center_block.x + 1;
+                    self.blocks[1].y = center_block.y;
+                    self.blocks[2].x = center_block.x - 1;
+                    self.blocks[2].y = center_block.y;
+                    self.blocks[3].x = center_block.x + 1;
+                    self.blocks[3].y = center_block.y + 1;
+                    self.rotation = Direction::Right;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Down);
+                    }
+                }
+                Direction::Left => {
+                    self.blocks[0].x = center_block.x;
+                    self.blocks[0].y = center_block.y - 1;
+                    self.blocks[1].x = center_block.x;
+                    self.blocks[1].y = center_block.y;
+                    self.blocks[2].x = center_block.x;
+                    self.blocks[2].y = center_block.y - 2;
+                    self.blocks[3].x = center_block.x + 1;
+                    self.blocks[3].y = center_block.y - 2;
+                    self.rotation = Direction::Down;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Left);
+                    }
+                }
+                Direction::Right => {
+                    self.blocks[0].x = center_block.x;
+                    self.blocks[0].y = center_block.y + 1;
+                    self.blocks[1].x = center_block.x;
+                    self.blocks[1].y = center_block.y;
+                    self.blocks[2].x = center_block.x;
+                    self.blocks[2].y = center_block.y - 1;
+                    self.blocks[3].x = center_block.x - 1;
+                    self.blocks[3].y = center_block.y + 1;
+                    self.rotation = Direction::Up;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Right);
+                    }
+                }
+            },
+            PieceType::ZL => match self.rotation {
+                Direction::Up => {
+                    self.blocks[1].x = center_block.x;
+                    self.blocks[1].y = center_block.y - 1;
+                    self.blocks[2].x = center_block.x + 1;
+                    self.blocks[2].y = center_block.y - 1;
+                    self.blocks[3].x = center_block.x + 1;
+                    self.blocks[3].y = center_block.y - 2;
+                    self.rotation = Direction::Left;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Up);
+                    }
+                }
+                Direction::Down => {
+                    self.blocks[1].x = center_block.x;
+                    self.blocks[1].y = center_block.y - 1;
+                    self.blocks[2].x = center_block.x + 1;
+                    self.blocks[2].y = center_block.y - 1;
+                    self.blocks[3].x = center_block.x + 1;
+                    self.blocks[3].y = center_block.y - 2;
+                    self.rotation = Direction::Right;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Down);
+                    }
+                }
+                Direction::Left => {
+                    self.blocks[1].x = center_block.x + 1;
+                    self.blocks[1].y = center_block.y;
+                    self.blocks[2].x = center_block.x;
+                    self.blocks[2].y = center_block.y - 1;
+                    self.blocks[3].x = center_block.x - 1;
+                    self.blocks[3].y = center_block.y - 1;
+                    self.rotation = Direction::Down;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Left);
+                    }
+                }
+                Direction::Right => {
+                    self.blocks[1].x = center_block.x + 1;
+                    self.blocks[1].y = center_block.y;
+                    self.blocks[2].x = center_block.x;
+                    self.blocks[2].y = center_block.y - 1;
+                    self.blocks[3].x = center_block.x - 1;
+                    self.blocks[3].y = center_block.y - 1;
+                    self.rotation = Direction::Up;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Right);
+                    }
+                }
+            },
+            PieceType::ZR => match self.rotation {
+                Direction::Up => {
+                    self.blocks[1].x = center_block.x;
+                    self.blocks[1].y = center_block.y - 1;
+                    self.blocks[2].x = center_block.x - 1;
+                    self.blocks[2].y = center_block.y - 1;
+                    self.blocks[3].x = center_block.x - 1;
+                    self.blocks[3].y = center_block.y - 2;
+                    self.rotation = Direction::Left;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Up);
+                    }
+                }
+                Direction::Down => {
+                    self.blocks[1].x = center_block.x;
+                    self.blocks[1].y = center_block.y - 1;
+                    self.blocks[2].x = center_block.x - 1;
+                    self.blocks[2].y = center_block.y - 1;
+                    self.blocks[3].x = center_block.x - 1;
+                    self.blocks[3].y = center_block.y - 2;
+                    self.rotation = Direction::Right;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Down);
+                    }
+                }
+                Direction::Left => {
+                    self.blocks[1].x = center_block.x - 1;
+                    self.blocks[1].y = center_block.y;
+                    self.blocks[2].x = center_block.x;
+                    self.blocks[2].y = center_block.y - 1;
+                    self.blocks[3].x = center_block.x + 1;
+                    self.blocks[3].y = center_block.y - 1;
+                    self.rotation = Direction::Down;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Left);
+                    }
+                }
+                Direction::Right => {
+                    self.blocks[1].x = center_block.x - 1;
+                    self.blocks[1].y = center_block.y;
+                    self.blocks[2].x = center_block.x;
+                    self.blocks[2].y = center_block.y - 1;
+                    self.blocks[3].x = center_block.x + 1;
+                    self.blocks[3].y = center_block.y - 1;
+                    self.rotation = Direction::Up;
+                    if !self.legal_move(grid) {
+                        self.restore_blocks(prev_blocks, Direction::Right);
+                    }
+                }
+            },
+        }
+    }
+}
+/// What one player controls on a board: the falling figure, the kept one and
+/// the queue. Boards have a single seat, in co-op every player has their own.
+#[derive(Clone, PartialEq, Debug)]
+struct Seat {
+    actual_figure: Option<Figure>,
+    keep_figure: Option<Figure>,
+    next_figures: Vec<Figure>,
+    // frames since the actual figure last fell
+    counter: u8,
+    // the last thing that moved the actual figure was a rotation, for T-spins
+    last_rotated: bool,
+    // figures taken out of the queue since the board was created, restarts
+    // included, so a bot can tell when a new figure comes
+    pieces: u64,
+    // columns right of the middle of the board where its figures spawn
+    shift: i16,
+}
+impl Seat {
+    fn new(rng: &mut Rand32, shift: i16) -> Self {
+        Seat {
+            actual_figure: None,
+            keep_figure: None,
+            next_figures: (0..7).map(|_| Figure::random(rng)).collect(),
+            counter: 0,
+            last_rotated: false,
+            pieces: 0,
+            shift,
+        }
+    }
+
+    /// `fig` where this seat's figures come into `grid`.
+    fn spawned(&self, fig: Figure, grid: Grid) -> Figure {
+        let mut fig = fig.spawned_on(grid);
+        for block in fig.blocks.iter_mut() {
+            block.x += self.shift;
+        }
+        fig
+    }
+}
+#[derive(Clone, PartialEq, Debug)]
+pub struct GameState {
+    // the players' figures, a single seat except in co-op
+    seats: Vec<Seat>,
+    gameover: bool,
+    pause: bool,
+    score: u32,
+    // frames played so far, used to timestamp the locked blocks
+    ticks: u64,
+    visibility: StackVisibility,
+    grid: Grid,
+    puzzle: Option<Puzzle>,
+    outcome: Outcome,
+    // attacks sent and garbage waiting to come up, in versus
+    garbage: Garbage,
+    rng: Rand32,
+    // blocks on board, and the cells they take, see `stack_changed`
+    static_blocks: Vec<Block>,
+    occupancy: Occupancy,
+    // the bot the player handed the game to, if they did
+    autoplay: Option<Bot>,
+    // what that bot looks for in the stack, and how long it thinks about each
+    // figure
+    bot_weights: Weights,
+    bot_time: Option<Duration>,
+    // the replay of the game and the high scores, for single player games
+    recorder: Option<Recorder>,
+    scoreboard: Option<Scoreboard>,
+    // the single player keys and how they repeat when held, from the settings
+    keys: KeyBindings,
+    handling: Handling,
+    held: Held,
+    // the modifiers the settings start a single player game with
+    gameplay: Gameplay,
+}
+impl Default for GameState {
+    fn default() -> Self {
+        GameState::new()
+    }
+}
+impl GameState {
+    /// Our new function will set up the initial state of our game.
+    pub fn new() -> Self {
+        GameState::with_seed(random_seed())
+    }
+
+    /// A game whose figures and garbage holes only depend on `seed`, given the
+    /// same inputs on the same frames two games with the same seed play the same.
+    pub fn with_seed(seed: u64) -> Self {
+        let mut rng = Rand32::new(seed);
+        let seats = vec![Seat::new(&mut rng, 0)];
+        GameState {
+            seats,
+            gameover: false,
+            score: 0,
+            pause: false,
+            ticks: 0,
+            visibility: StackVisibility::Visible,
+            grid: Grid::normal(),
+            puzzle: None,
+            outcome: Outcome::Playing,
+            garbage: Garbage::new(),
+            rng,
+            static_blocks: vec![],
+            occupancy: Occupancy::of(&[], Grid::normal()),
+            autoplay: None,
+            bot_weights: Weights::default(),
+            bot_time: None,
+            recorder: None,
+            scoreboard: None,
+            keys: KeyBindings::arrows(),
+            handling: Handling::default(),
+            held: Held::default(),
+            gameplay: Gameplay::default(),
+        }
+    }
+
+    /// A single player game recorded for a replay.
+    pub fn recorded() -> Self {
+        let seed = random_seed();
+        let mut state = GameState::with_seed(seed);
+        state.recorder = Some(Recorder::new(seed, state.grid, state.visibility));
+        state.scoreboard = Some(Scoreboard::default());
+        state
+    }
+
+    /// A board twice as wide for two players, each one with their own figures,
+    /// queue and kept figure, spawning over their half of it.
+    pub fn coop(seed: u64) -> Self {
+        let mut state = GameState::with_seed(seed);
+        state.grid = Grid::wide();
+        state.seats[0].shift = -GRID_WIDTH / 2;
+        let seat = Seat::new(&mut state.rng, GRID_WIDTH / 2);
+        state.seats.push(seat);
+        state.stack_changed();
+        state
+    }
+
+    /// A game that starts with the stack and the pieces of `puzzle`.
+    pub fn from_puzzle(puzzle: Puzzle) -> Self {
+        let mut state = GameState::new();
+        state.puzzle = Some(puzzle);
+        state.restart();
+        state
+    }
+
+    /// Takes the first figure of the queue of seat `n`, where that seat spawns,
+    /// and refills the queue. Puzzles only have the pieces they were given, so
+    /// once the queue is empty the kept figure is the last one left and then
+    /// there are no more.
+    fn next_figure(&mut self, n: usize) -> Option<Figure> {
+        let grid = self.grid;
+        let seat = &mut self.seats[n];
+        seat.pieces += 1;
+        if self.puzzle.is_none() {
+            seat.next_figures.push(Figure::random(&mut self.rng));
+        } else if seat.next_figures.is_empty() {
+            let kept = seat.keep_figure.take();
+            return kept.map(|fig| seat.spawned(fig, grid));
+        }
+        let fig = seat.next_figures.remove(0);
+        Some(seat.spawned(fig, grid))
+    }
+
+    /// Puts the next figure of seat `n` on the board. While the figure of
+    /// another seat is in the way it waits in the queue.
+    fn spawn(&mut self, n: usize) {
+        let seat = &self.seats[n];
+        if let Some(fig) = seat.next_figures.first() {
+            if self.hits_partners(n, &seat.spawned(*fig, self.grid)) {
+                return;
+            }
+        }
+        self.seats[n].actual_figure = self.next_figure(n);
+    }
+
+    /// `fig` shares a cell with the figure of a seat other than `n`.
+    fn hits_partners(&self, n: usize, fig: &Figure) -> bool {
+        self.seats.iter().enumerate().any(|(i, seat)| {
+            i != n
+                && seat
+                    .actual_figure
+                    .is_some_and(|other| overlaps(fig, &other))
+        })
+    }
+
+    /// `fig`, moved by seat `n`, runs into the stack or into another figure.
+    fn blocked(&self, n: usize, fig: &Figure) -> bool {
+        self.occupancy.collides(fig) || self.hits_partners(n, fig)
+    }
+
+    /// Updates the cells taken after `static_blocks` or the grid changed.
+    fn stack_changed(&mut self) {
+        self.occupancy = Occupancy::of(&self.static_blocks, self.grid);
+    }
+
+    /// The level of the game, one more for every ten lines cleared.
+    pub fn level(&self) -> u32 {
+        1 + self.score / LINES_PER_LEVEL
+    }
+
+    /// Frames the figures take to fall a row, fewer the higher the level.
+    fn fall_frames(&self) -> u32 {
+        let faster = (self.level() - 1).saturating_mul(FALL_STEP);
+        SLOWEST_FALL.saturating_sub(faster).max(FASTEST_FALL)
+    }
+
+    /// How far the stack reaches from the floor.
+    pub fn stack_height(&self) -> i16 {
+        let grid = self.grid;
+        self.static_blocks
+            .iter()
+            .map(|block| (grid.floor_row() - block.y) * grid.fall() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The cells of the stack, as their column and row from the top.
+    pub fn stack_cells(&self) -> Vec<(i16, i16)> {
+        self.static_blocks
+            .iter()
+            .map(|block| (block.x, block.y))
+            .collect()
+    }
+
+    /// The letter of the piece falling on the first seat.
+    pub fn falling_piece(&self) -> Option<char> {
+        self.seats[0]
+            .actual_figure
+            .map(|fig| fig.piece_type.letter())
+    }
+
+    /// Clears the board to start a new game.
+    fn restart(&mut self) {
+        for seat in self.seats.iter_mut() {
+            seat.actual_figure = None;
+            seat.keep_figure = None;
+            seat.last_rotated = false;
+        }
+        self.static_blocks = vec![];
+        self.gameover = false;
+        self.score = 0;
+        self.ticks = 0;
+        self.garbage = Garbage::new();
+        if let Some(puzzle) = &self.puzzle {
+            let grid = self.grid;
+            self.static_blocks = puzzle.stack.iter().map(|b| grid.orient(*b)).collect();
+            self.seats[0].next_figures = puzzle
+                .pieces
+                .iter()
+                .map(|piece| Figure::new(piece.number()))
+                .collect();
+            self.outcome = Outcome::Playing;
+        }
+        self.stack_changed();
+    }
+
+    /// Starts the game again. A recorded game saves its replay and starts a new
+    /// one from a new seed, so it can be dealt again.
+    fn new_game(&mut self) {
+        if self.recorder.is_some() {
+            self.save_replay();
+            let seed = random_seed();
+            let fresh = GameState::with_seed(seed);
+            self.rng = fresh.rng;
+            self.seats = fresh.seats;
+            // the replay starts playing, like the game it deals again
+            self.pause = false;
+            self.recorder = Some(Recorder::new(seed, self.grid, self.visibility));
+        }
+        if let Some(scoreboard) = &mut self.scoreboard {
+            scoreboard.reset();
+        }
+        self.restart();
+    }
+
+    /// Writes the replay of the game once, when it's recorded.
+    fn save_replay(&mut self) {
+        let (score, gameover) = (self.score, self.gameover);
+        if let Some(recorder) = &mut self.recorder {
+            match recorder.save(score, gameover) {
+                Ok(Some(path)) => println!("replay saved to {}", path.display()),
+                Ok(None) => (),
+                Err(e) => eprintln!("could not save the replay: {}", e),
+            }
+        }
+    }
+
+    /// Plays `input`, recording it when the game is recorded.
+    fn play_input(&mut self, input: Input) {
+        self.record(Event::Input(input));
+        self.handle_input(input);
+    }
+
+    /// Plays the held key again, first after the delay and then at the rate
+    /// of the handling settings.
+    fn repeat_held(&mut self) {
+        let Some((input, times)) = self.held.repeat(&self.handling, self.grid.width) else {
+            return;
+        };
+        for _ in 0..times {
+            // only the repeats that change something go in the replay
+            let seat = self.seats[0].clone();
+            self.handle_input(input);
+            if self.seats[0] == seat {
+                break;
+            }
+            self.record(Event::Input(input));
+        }
+    }
+
+    fn record(&mut self, event: Event) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(event);
+        }
+    }
+
+    /// Ends the puzzle once its goal is met, this runs every time a piece locks.
+    fn check_puzzle(&mut self, cleared: u32, tspin: bool) {
+        if let Some(puzzle) = &self.puzzle {
+            let board_empty = self.static_blocks.is_empty();
+            if puzzle.goal.is_met(self.score, cleared, tspin, board_empty) {
+                self.outcome = Outcome::Solved;
+                self.gameover = true;
+            }
+        }
+    }
+
+    /// Runs one frame of the game: the gravity, locking the figure when it lands
+    /// and clearing the full lines.
+    pub fn tick(&mut self) {
+        // We check to see if the game is over. If not, we'll update. If so, we'll just do nothing.
+        let mut iter1 = self.static_blocks.iter().peekable();
+        while iter1.peek().is_some() && !self.gameover {
+            let block = iter1.next().unwrap();
+            if !self.grid.is_above(self.grid.spawn_row(), block.y) {
+                self.gameover = true;
+            }
+        }
+        if self.gameover {
+            // a puzzle that ends without being solved is failed
+            if self.puzzle.is_some() && self.outcome == Outcome::Playing {
+                self.outcome = Outcome::Failed;
+            }
+            return;
+        }
+        if !self.pause {
+            self.ticks += 1;
+        }
+        for n in 0..self.seats.len() {
+            if !self.gameover {
+                self.tick_seat(n);
+            }
+        }
+    }
+
+    /// Runs one frame for the figure of seat `n`: the gravity, locking it when
+    /// it lands and clearing the full lines.
+    fn tick_seat(&mut self, n: usize) {
+        let Some(mut fig) = self.seats[n].actual_figure else {
+            self.spawn(n);
+            // only puzzles can run out of figures
+            let seat = &self.seats[n];
+            if seat.actual_figure.is_none() && seat.next_figures.is_empty() {
+                self.gameover = true;
+            }
+            return;
+        };
+        let grid = self.grid;
+        if u32::from(self.seats[n].counter) >= self.fall_frames() && !self.pause {
+            let prev = fig;
+            for block in fig.blocks.iter_mut() {
+                block.y += grid.fall();
+            }
+            // a figure under another one holds it up without locking it
+            if self.blocked(n, &fig) {
+                fig.restore_blocks(prev.blocks, fig.rotation);
+            } else {
+                self.seats[n].last_rotated = false;
+            }
+            self.seats[n].counter = 0;
+        }
+        if !self.pause {
+            self.seats[n].counter += 1;
+        }
+        self.seats[n].actual_figure = Some(fig);
+        let (tspin, mini) = if self.seats[n].last_rotated {
+            tspin_kind(&fig, |x, y| {
+                grid.ilegal_coords(x, y) || self.occupancy.is_taken(x, y)
+            })
+        } else {
+            (false, false)
+        };
+        let mut locked = false;
+        // the figure locks once it lies on the floor or on the stack
+        let landed = fig.some_block_is_in_y(grid.floor_row())
+            || fig
+                .blocks
+                .iter()
+                .any(|block| self.occupancy.is_taken(block.x, block.y + grid.fall()));
+        if landed {
+            for block in fig.blocks.into_iter() {
+                self.static_blocks.push(Block {
+                    locked_at: self.ticks,
+                    ..block
+                });
+            }
+            self.stack_changed();
+            locked = true;
+            self.seats[n].actual_figure = None;
+            self.spawn(n);
+        }
+        // the blocks of every row are counted once, then the full rows go
+        // and each block falls as many rows as were cleared under it
+        let mut row_blocks = vec![0; grid.height as usize];
+        for block in self.static_blocks.iter() {
+            if let Some(n) = row_blocks.get_mut(block.y as usize) {
+                *n += 1;
+            }
+        }
+        let full: Vec<i16> = (0..grid.height)
+            .filter(|y| row_blocks[*y as usize] == grid.width)
+            .collect();
+        let cleared = full.len() as u32;
+        if cleared > 0 {
+            self.score += cleared;
+            self.static_blocks.retain(|bl| !full.contains(&bl.y));
+            for bl in self.static_blocks.iter_mut() {
+                let under = full.iter().filter(|y| grid.is_above(bl.y, **y)).count();
+                bl.y += grid.fall() * under as i16;
+            }
+            self.stack_changed();
+            // the stack coming down can fall into the figure of another seat,
+            // which gets pushed up out of it
+            for seat in self.seats.iter_mut() {
+                if let Some(fig) = seat.actual_figure.as_mut() {
+                    while self.occupancy.collides(fig) {
+                        for block in fig.blocks.iter_mut() {
+                            block.y -= grid.fall();
+                        }
+                    }
+                }
+            }
+        }
+        if locked {
+            self.check_puzzle(cleared, tspin);
+            self.garbage.lock(Clear {
+                lines: cleared,
+                tspin,
+                mini,
+                perfect: cleared > 0 && self.static_blocks.is_empty(),
+            });
+            // clearing lines attacks, otherwise the garbage we got comes up
+            if cleared == 0 {
+                self.add_garbage();
+            }
+        }
+    }
+
+    /// Draws the board, the figures and the score where `layout` says. This
+    /// doesn't clear or present the screen so several boards can share a frame.
+    pub fn draw_board(&self, ctx: &mut Context, layout: &Layout) -> GameResult {
+        /*let mut init_pos = 640.0;
+        let mut init_pos_vert = 48.0;
+        for _n in 0..20 {
+            for _n in 0..10 {
+                let rect = graphics::Rect::new(init_pos, init_pos_vert, 43.0, 43.0);
+                let r1 = graphics::Mesh::new_rectangle(
+                    ctx,
+                    graphics::DrawMode::fill(),
+                    rect,
+                    Color::BLUE,
+                )?;
+                graphics::draw(ctx, &r1, DrawParam::default())?;
+                init_pos += 48.0;
+            }
+            init_pos = 640.0;
+            init_pos_vert += 48.0;
+        }*/
+
+        let cell = self.grid.cell_size() as f32;
+        let mut init_pos = layout.board_x - 5.0;
+        let mut init_pos_vert = (GRID_CELL_SIZE - 5) as f32;
+        for _n in 0..=self.grid.width {
+            let rect = graphics::Rect::new(
+                init_pos,
+                init_pos_vert,
+                5.0,
+                self.grid.height as f32 * cell + 5.0,
+            );
+            let r1 = graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                rect,
+                Color::new(0.251, 0.251, 0.251, 1.0),
+            )?;
+            graphics::draw(ctx, &r1, DrawParam::default())?;
+            init_pos += cell;
+        }
+        init_pos = layout.board_x - 5.0;
+        for _n in 0..=self.grid.height {
+            let rect = graphics::Rect::new(
+                init_pos,
+                init_pos_vert,
+                self.grid.width as f32 * cell + 5.0,
+                5.0,
+            );
+            let r1 = graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                rect,
+                Color::new(0.251, 0.251, 0.251, 1.0),
+            )?;
+            graphics::draw(ctx, &r1, DrawParam::default())?;
+            init_pos_vert += cell;
+        }
+
+        if !self.gameover {
+            // this will draw the actual_figure of every seat
+            let figures = self
+                .seats
+                .iter()
+                .filter_map(|seat| seat.actual_figure.as_ref());
+            for block in figures.flat_map(|fig| fig.blocks.iter()) {
+                let rect = self.grid.block_rect(block, layout.board_x);
+                let r1 = graphics::Mesh::new_rectangle(
+                    ctx,
+                    graphics::DrawMode::fill(),
+                    rect,
+                    block.color,
+                )?;
+                graphics::draw(ctx, &r1, DrawParam::default())?;
+            }
+            // this will draw the static_blocks, hiding or fading them depending on the mode
+            for block in self.static_blocks.iter() {
+                let alpha = self.visibility.alpha(block.locked_at, self.ticks);
+                if alpha <= 0.0 {
+                    continue;
+                }
+                let rect = self.grid.block_rect(block, layout.board_x);
+                let r1 = graphics::Mesh::new_rectangle(
+                    ctx,
+                    graphics::DrawMode::fill(),
+                    rect,
+                    Color {
+                        a: block.color.a * alpha,
+                        ..block.color
+                    },
+                )?;
+                graphics::draw(ctx, &r1, DrawParam::default())?;
+            }
+            // this will draw the garbage waiting to come up, as a red meter
+            // beside the board growing from the floor
+            let pending = (self.garbage.pending() as i16).min(self.grid.height);
+            if pending > 0 {
+                let far_row = self.grid.floor_row() - self.grid.fall() * (pending - 1);
+                let top_row = far_row.min(self.grid.floor_row());
+                let rect = graphics::Rect::new(
+                    layout.board_x - 20.0,
+                    GRID_CELL_SIZE as f32 + top_row as f32 * cell,
+                    10.0,
+                    pending as f32 * cell - 5.0,
+                );
+                let r1 = graphics::Mesh::new_rectangle(
+                    ctx,
+                    graphics::DrawMode::fill(),
+                    rect,
+                    Color::RED,
+                )?;
+                graphics::draw(ctx, &r1, DrawParam::default())?;
+            }
+            // This will draw the queue of figures and the keep_figure of every
+            // seat, the ones after the first further right
+            for (n, seat) in self.seats.iter().enumerate() {
+                let seat_x = n as f32 * layout.seat_spacing;
+                let mut right_pos_y: f32 = layout.preview_y;
+                for figure in seat.next_figures.iter() {
+                    for block in figure.blocks {
+                        let (x, y) = self.grid.orient_preview(block).preview_pos();
+                        let rect = graphics::Rect::new(
+                            seat_x + layout.queue_x + x,
+                            y + right_pos_y,
+                            (GRID_CELL_SIZE - 5) as f32 / 2.0,
+                            (GRID_CELL_SIZE - 5) as f32 / 2.0,
+                        );
+                        let r1 = graphics::Mesh::new_rectangle(
+                            ctx,
+                            graphics::DrawMode::fill(),
+                            rect,
+                            block.color,
+                        )?;
+                        graphics::draw(ctx, &r1, DrawParam::default())?;
+                    }
+                    right_pos_y += (GRID_CELL_SIZE) as f32 * 2.5;
+                }
+                // this will draw the keep_figure
+                if let Some(figure) = seat.keep_figure {
+                    let right_pos_y: f32 = layout.preview_y;
+                    for block in figure.blocks {
+                        let (x, y) = self.grid.orient_preview(block).preview_pos();
+                        let rect = graphics::Rect::new(
+                            seat_x + layout.keep_x + x,
+                            y + right_pos_y,
+                            (GRID_CELL_SIZE - 5) as f32 / 2.0,
+                            (GRID_CELL_SIZE - 5) as f32 / 2.0,
+                        );
+                        let r1 = graphics::Mesh::new_rectangle(
+                            ctx,
+                            graphics::DrawMode::fill(),
+                            rect,
+                            block.color,
+                        )?;
+                        graphics::draw(ctx, &r1, DrawParam::default())?;
+                    }
+                }
+            }
+            let mut string = format!("Score : {}\nLevel : {}", self.score, self.level());
+            match self.visibility {
+                StackVisibility::Visible => {}
+                StackVisibility::Fading => string.push_str("\nFading stack"),
+                StackVisibility::Invisible => string.push_str("\nInvisible stack"),
+            }
+            if self.grid.is_big() {
+                string.push_str("\nBig mode");
+            }
+            if self.grid.mirrored {
+                string.push_str("\nMirrored");
+            }
+            if self.grid.upside_down {
+                string.push_str("\nUpside down");
+            }
+            if self.garbage.combo() > 0 {
+                string.push_str(&format!("\nCombo : {}", self.garbage.combo()));
+            }
+            if self.garbage.back_to_back() {
+                string.push_str("\nBack-to-back");
+            }
+            if self.autoplay.is_some() {
+                string.push_str("\nBot playing");
+            }
+            if let Some(puzzle) = &self.puzzle {
+                string.push_str(&format!(
+                    "\n\n{}\nGoal : {}\nPieces left : {}",
+                    puzzle.name,
+                    puzzle.goal,
+                    self.seats[0].next_figures.len() + self.seats[0].keep_figure.iter().count()
+                ));
+            }
+            let mut text = Text::new(string);
+            //let path = env::current_dir()?.join("resources/Hack_Regular_Nerd_Font.ttf");
+            //            let font = Font::new(ctx, "/Hack_Regular_Nerd_Font.ttf").expect("Font not found bro");
+            let scale = PxScale::from(32.0);
+            text.set_font(Font::default(), scale);
+            graphics::draw(ctx, &text, DrawParam::default().dest(layout.score))?;
+        } else {
+            let string = match self.outcome {
+                Outcome::Solved => "Puzzle solved :)  press R to play it again".to_string(),
+                Outcome::Failed => "Puzzle failed :(  press R to try again".to_string(),
+                Outcome::Playing => format!(
+                    "Game Over :(  press R to restart a new game , your score was {}",
+                    self.score
+                ),
+            };
+            let mut text = Text::new(string);
+            // Maybe i can put my own custom font with this
+            //            let font = Font::new(ctx, "/Hack_Regular_Nerd_Font.ttf").expect("Font not found bro");
+            let scale = PxScale::from(32.0);
+            let font = Font::default();
+            text.set_font(font, scale);
+            text.set_bounds(
+                Point2 {
+                    x: layout.message_width,
+                    y: f32::INFINITY,
+                },
+                graphics::Align::Left,
+            );
+            graphics::draw(ctx, &text, DrawParam::default().dest(layout.message))?;
+        }
+        Ok(())
+    }
+
+    /// Moves, rotates or keeps the actual figure.
+    pub fn handle_input(&mut self, input: Input) {
+        self.handle_seat_input(0, input);
+    }
+
+    /// Moves, rotates or keeps the actual figure of seat `n`, which can't go
+    /// through the figures of the other seats.
+    pub fn handle_seat_input(&mut self, n: usize, input: Input) {
+        if self.gameover {
+            return;
+        }
+        let grid = self.grid;
+        let Some(mut fig) = self.seats[n].actual_figure else {
+            return;
+        };
+        let mut var_block: i16 = 0;
+        match input {
+            Input::Hold => {
+                let seat = &self.seats[n];
+                // the figure coming instead has to fit where it spawns
+                let coming = seat.keep_figure.or(seat.next_figures.first().copied());
+                if coming.is_some_and(|coming| self.hits_partners(n, &seat.spawned(coming, grid))) {
+                    return;
+                }
+                let number = fig.piece_type.number();
+                match self.seats[n].keep_figure {
+                    None => {
+                        self.seats[n].keep_figure = Some(Figure::new(number));
+                        self.seats[n].actual_figure = self.next_figure(n);
+                    }
+                    Some(figure) => {
+                        let seat = &mut self.seats[n];
+                        seat.keep_figure = Some(Figure::new(number));
+                        let number = figure.piece_type.number();
+                        seat.actual_figure = Some(seat.spawned(Figure::new(number), grid));
+                    }
+                }
+                self.seats[n].last_rotated = false;
+                return;
+            }
+            Input::Rotate => {
+                let prev_blocks = fig.blocks;
+                let prev_rotation = fig.rotation;
+                fig.rotate(grid);
+                if self.blocked(n, &fig) {
+                    fig.restore_blocks(prev_blocks, prev_rotation);
+                }
+                self.seats[n].last_rotated = fig.blocks != prev_blocks;
+            }
+            Input::SoftDrop => {
+                let prev_blocks = fig.blocks;
+                for block in fig.blocks.iter_mut() {
+                    block.y += grid.fall();
+                }
+                self.seats[n].counter = 0;
+                if self.blocked(n, &fig) {
+                    fig.restore_blocks(prev_blocks, fig.rotation);
+                } else {
+                    self.seats[n].last_rotated = false;
+                }
+            }
+            Input::Left => var_block = -1,
+            Input::Right => var_block = 1,
+        }
+        if var_block != 0 {
+            let prev_fig = fig;
+            for block in fig.blocks.iter_mut() {
+                block.x += var_block;
+            }
+            if self.blocked(n, &fig) || !fig.legal_move(grid) {
+                fig.restore_blocks(prev_fig.blocks, fig.rotation);
+            } else {
+                self.seats[n].last_rotated = false;
+            }
+        }
+        self.seats[n].actual_figure = Some(fig);
+    }
+
+    /// Pushes the stack away from the floor and fills the rows left under it
+    /// with the garbage that waited long enough, each attack with its hole in
+    /// its own column.
+    fn add_garbage(&mut self) {
+        let grid = self.grid;
+        for rows in self.garbage.take_ready(self.ticks) {
+            let rows = rows as i16;
+            for block in self.static_blocks.iter_mut() {
+                block.y -= grid.fall() * rows;
+            }
+            let hole = self.rng.rand_range(0..grid.width as u32) as i16;
+            for n in 0..rows {
+                let y = grid.floor_row() - grid.fall() * n;
+                for x in (0..grid.width).filter(|x| *x != hole) {
+                    self.static_blocks.push(Block {
+                        locked_at: self.ticks,
+                        ..Block::new(x, y, GARBAGE_COLOR)
+                    });
+                }
+            }
+        }
+        self.stack_changed();
+        // the figures may already be where the stack went up to
+        let mut figures = self
+            .seats
+            .iter()
+            .filter_map(|seat| seat.actual_figure.as_ref());
+        if figures.any(|fig| self.occupancy.collides(fig)) {
+            self.gameover = true;
+        }
+    }
+}
+/// The cells taken by the stack, so checking a move doesn't go through every
+/// block of it. This runs for every move of every board. Blocks pushed past
+/// the top of the grid by garbage are kept apart.
+#[derive(Clone, PartialEq, Debug)]
+struct Occupancy {
+    width: i16,
+    height: i16,
+    cells: Vec<bool>,
+    outside: Vec<(i16, i16)>,
+}
+impl Occupancy {
+    fn of(static_blocks: &[Block], grid: Grid) -> Self {
+        let mut occupancy = Occupancy {
+            width: grid.width,
+            height: grid.height,
+            cells: vec![false; (grid.width * grid.height) as usize],
+            outside: vec![],
+        };
+        for block in static_blocks.iter() {
+            match occupancy.index(block.x, block.y) {
+                Some(i) => occupancy.cells[i] = true,
+                None => occupancy.outside.push((block.x, block.y)),
+            }
+        }
+        occupancy
+    }
+
+    fn index(&self, x: i16, y: i16) -> Option<usize> {
+        if (0..self.width).contains(&x) && (0..self.height).contains(&y) {
+            Some((y * self.width + x) as usize)
+        } else {
+            None
+        }
+    }
+
+    /// A block of the stack is at `x` and `y`.
+    fn is_taken(&self, x: i16, y: i16) -> bool {
+        match self.index(x, y) {
+            Some(i) => self.cells[i],
+            None => self.outside.contains(&(x, y)),
+        }
+    }
+
+    /// The figure overlaps the stack.
+    fn collides(&self, fig: &Figure) -> bool {
+        fig.blocks
+            .iter()
+            .any(|block| self.is_taken(block.x, block.y))
+    }
+}
+/// The figures share a cell.
+fn overlaps(a: &Figure, b: &Figure) -> bool {
+    a.blocks.iter().any(|block_a| {
+        b.blocks
+            .iter()
+            .any(|block_b| block_a.x == block_b.x && block_a.y == block_b.y)
+    })
+}
+/// A seed for the random number generators, from the OS.
+fn random_seed() -> u64 {
+    let mut seed: [u8; 8] = [0; 8];
+    getrandom::getrandom(&mut seed[..]).expect("Could not create RNG seed");
+    u64::from_ne_bytes(seed)
+}
+/// A T locked right after a rotation is a T-spin when at least three of the
+/// four cells diagonal to its center are walls, floor or blocks, as `taken`
+/// says, and a mini one when only one of them is on the side the T points to.
+/// Gives back whether `fig` makes a T-spin and whether it's a mini one.
+fn tspin_kind(fig: &Figure, taken: impl Fn(i16, i16) -> bool) -> (bool, bool) {
+    if fig.piece_type != PieceType::T {
+        return (false, false);
+    }
+    // the center is the block touching the other three, and the T points to
+    // the one of them with no block across the center
+    let touching = |a: &Block, b: &Block| (a.x - b.x).abs() + (a.y - b.y).abs() == 1;
+    let center = fig
+        .blocks
+        .iter()
+        .find(|a| fig.blocks.iter().filter(|b| touching(a, b)).count() == 3);
+    let Some(center) = center else {
+        return (false, false);
+    };
+    let across = |a: &Block| {
+        fig.blocks
+            .iter()
+            .any(|b| b.x == 2 * center.x - a.x && b.y == 2 * center.y - a.y)
+    };
+    let Some(nub) = fig
+        .blocks
+        .iter()
+        .find(|a| touching(a, center) && !across(a))
+    else {
+        return (false, false);
+    };
+    let (dx, dy) = (nub.x - center.x, nub.y - center.y);
+    let corners: Vec<(i16, i16)> = [(-1, -1), (1, -1), (-1, 1), (1, 1)]
+        .into_iter()
+        .filter(|(x, y)| taken(center.x + x, center.y + y))
+        .collect();
+    let front = corners.iter().filter(|(x, y)| x * dx + y * dy == 1).count();
+    let tspin = corners.len() >= 3;
+    (tspin, tspin && front < 2)
+}
+impl event::EventHandler<ggez::GameError> for GameState {
+    /// Update will happen on every frame before it is drawn. This is where we update
+    /// our game state to react to whatever is happening in the game world.
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        // Rely on ggez's built-in timer for deciding when to update the game, and how many times.
+        // If the update is early, there will be no cycles, otherwises, the logic will run once for each
+        // frame fitting in the time since the last update.
+        while timer::check_update_time(ctx, FPS) {
+            if self.autoplay.is_none() {
+                self.repeat_held();
+            }
+            if let Some(mut bot) = self.autoplay.take() {
+                if let Some(input) = bot.input(self) {
+                    self.play_input(input);
+                }
+                if let Some(scoreboard) = &mut self.scoreboard {
+                    scoreboard.assist();
+                }
+                self.autoplay = Some(bot);
+            }
+            // the frames after the game is over don't go in the replay
+            let over = self.gameover;
+            self.tick();
+            if let (Some(recorder), false) = (&mut self.recorder, over) {
+                recorder.tick();
+            }
+        }
+        if self.gameover {
+            self.save_replay();
+            let level = self.level();
+            if let (Some(scoreboard), Some(recorder)) = (&mut self.scoreboard, &self.recorder) {
+                let played = (self.score, level, self.ticks);
+                scoreboard.game_over(ctx, self.grid, played, recorder.seed());
+            }
+        }
+        Ok(())
+    }
+
+    /// draw is where we should actually render the game's current state.
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        // First we clear the screen to a nice (well, maybe pretty glaring ;)) green
+        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
+
+        let layout = Layout::single();
+        self.draw_board(ctx, &layout)?;
+        if let (true, Some(scoreboard)) = (self.gameover, &self.scoreboard) {
+            let dest = Point2 {
+                x: layout.board_x,
+                y: 4.0 * GRID_CELL_SIZE as f32,
+            };
+            scoreboard.draw(ctx, dest)?;
+        }
+        // Then we tell the snake and the food to draw themselves
+        // Finally we call graphics::present to cycle the gpu's framebuffer and display
+        // the new frame we just drew.
+        graphics::present(ctx)?;
+        // We yield the current thread until the next update
+        ggez::timer::yield_now();
+        // And return success.
+        Ok(())
+    }
+
+    /// key_down_event gets fired when a key gets pressed.
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        repeat: bool,
+    ) {
+        // the keys type the name of a new high score until it's entered
+        if let Some(scoreboard) = self.scoreboard.as_mut().filter(|s| s.entering_name()) {
+            match keycode {
+                KeyCode::Return | KeyCode::NumpadEnter => scoreboard.confirm_name(),
+                KeyCode::Back => scoreboard.erase_char(),
+                _ => (),
+            }
+            return;
+        }
+        if let KeyCode::R = keycode {
+            self.new_game();
+        } else if let KeyCode::P = keycode {
+            self.record(Event::Pause);
+            self.pause = !self.pause;
+        } else if let KeyCode::I = keycode {
+            self.record(Event::Visibility);
+            self.visibility = self.visibility.next();
+        } else if let KeyCode::A = keycode {
+            // the bot plays until the key is pressed again
+            self.autoplay = match self.autoplay {
+                Some(_) => None,
+                None => {
+                    let mut bot = Bot::new(AUTOPLAY_DELAY);
+                    bot.weights = self.bot_weights;
+                    bot.lookahead = Some(Lookahead {
+                        time: self.bot_time,
+                        ..Lookahead::default()
+                    });
+                    Some(bot)
+                }
+            };
+        } else if let (KeyCode::B, None) = (keycode, &self.puzzle) {
+            // switching between Big mode and the standard grid needs a new game
+            let grid = if self.grid.is_big() {
+                Grid::normal()
+            } else {
+                Grid::big()
+            };
+            self.grid = Grid {
+                mirrored: self.grid.mirrored,
+                upside_down: self.grid.upside_down,
+                ..grid
+            };
+            self.new_game();
+        } else if let KeyCode::M = keycode {
+            // the modifiers change the rules so they also start a new game
+            self.grid.mirrored = !self.grid.mirrored;
+            self.new_game();
+        } else if let KeyCode::G = keycode {
+            self.grid.upside_down = !self.grid.upside_down;
+            self.new_game();
+        }
+        // held keys repeat at the rate of the settings, not the system's
+        if let (None, Some(input), false) = (&self.autoplay, self.keys.input(keycode), repeat) {
+            self.play_input(input);
+            self.held.press(input);
+        }
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
+        if let Some(input) = self.keys.input(keycode) {
+            self.held.release(input);
+        }
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+        if let Some(scoreboard) = &mut self.scoreboard {
+            scoreboard.type_char(character);
+        }
+    }
+
+    /// The replay of a game quit before it's over is saved too.
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        self.save_replay();
+        false
+    }
+}
+
+pub fn main() -> GameResult {
+    // `tetris --puzzle <file>` plays a puzzle, `tetris --versus` a two player
+    // game and `tetris --host <port>` or `tetris --connect <address> [<name>
+    // [<lobby>]]` a two player game over the network instead of a normal game,
+    // the lobby is only for `tetris-server`. The host adds `--rollback` to use
+    // rollbacks instead of lockstep, and `--versus --latency <ms>` plays with
+    // rollbacks through a simulated connection. `--versus --cpu [<level>]`
+    // plays against a bot, easy, medium, hard or expert. `tetris --spectate <address>`
+    // watches a game that was started with `--spectators <port>`, `tetris
+    // --royale [<bots>]` plays a battle royale against 49 bots or the number given
+    // and `tetris --coop` a two player game on one wide board. `tetris --tbp
+    // <command> [<args>...]` lets an external bot play through the Tetris Bot
+    // Protocol. `tetris --train [<options>]` evolves the weights of the bot with no
+    // window, see `src/train.rs`, and `--weights <file>` has the bot of a single
+    // player game play with the weights saved, `--time-ms <ms>` thinking at most
+    // that long about each figure. `tetris --benchmark [<options>]`
+    // compares the bots on the same games, see `src/benchmark.rs`, and `tetris
+    // --env` serves a reinforcement learning environment on stdin and stdout,
+    // see `src/env.rs`. `tetris --replay <file>` plays back a replay saved by
+    // a single player game, `tetris verify <file>` checks its score with no
+    // window and `tetris --gif <file> [<options>]` draws a clip of it, see
+    // `src/clip.rs`
+    let mut args: Vec<String> = std::env::args().collect();
+    let spectators = match args.iter().position(|arg| arg == "--spectators") {
+        Some(i) => {
+            let port = args
+                .get(i + 1)
+                .and_then(|port| port.parse().ok())
+                .ok_or_else(|| {
+                    ggez::GameError::ConfigError("--spectators needs a port".to_string())
+                })?;
+            args.drain(i..i + 2);
+            Some(port)
+        }
+        None => None,
+    };
+    let weights = match args.iter().position(|arg| arg == "--weights") {
+        Some(i) => {
+            let path = args.get(i + 1).ok_or_else(|| {
+                ggez::GameError::ConfigError(
+                    "--weights needs the path of a weights file".to_string(),
+                )
+            })?;
+            let weights = Weights::load(path)?;
+            args.drain(i..i + 2);
+            weights
+        }
+        None => Weights::default(),
+    };
+    let bot_time = match args.iter().position(|arg| arg == "--time-ms") {
+        Some(i) => {
+            let ms = args
+                .get(i + 1)
+                .and_then(|ms| ms.parse().ok())
+                .ok_or_else(|| {
+                    ggez::GameError::ConfigError(
+                        "--time-ms needs a count of milliseconds".to_string(),
+                    )
+                })?;
+            args.drain(i..i + 2);
+            Some(Duration::from_millis(ms))
+        }
+        None => None,
+    };
+    let mode = args.get(1).map(String::as_str);
+    match mode {
+        Some("--train") => return train::train(&TrainOptions::parse(&args[2..])?),
+        Some("--benchmark") => return benchmark::benchmark(&BenchmarkOptions::parse(&args[2..])?),
+        Some("--env") => return env::serve(),
+        Some("--gif") => return clip::export(&GifOptions::parse(&args[2..])?),
+        Some("verify" | "--verify") => {
+            return match args.get(2) {
+                Some(path) => replay::verify(path),
+                None => Err(ggez::GameError::ConfigError(
+                    "verify needs the path of a replay file".to_string(),
+                )),
+            }
+        }
+        _ => (),
+    }
+    let settings = Settings::load(SETTINGS_FILE)?;
+    let puzzle = match (mode, args.get(2)) {
+        (Some("--puzzle"), Some(path)) => Some(Puzzle::load(path)?),
+        (Some("--puzzle"), None) => {
+            return Err(ggez::GameError::ConfigError(
+                "--puzzle needs the path of a puzzle file".to_string(),
+            ))
+        }
+        _ => None,
+    };
+    let replay = match (mode, args.get(2)) {
+        (Some("--replay"), Some(path)) => Some(Replay::load(path)?),
+        (Some("--replay"), None) => {
+            return Err(ggez::GameError::ConfigError(
+                "--replay needs the path of a replay file".to_string(),
+            ))
+        }
+        _ => None,
+    };
+    // the other player is found before opening the window, so the game starts
+    // on both sides at the same time
+    let link = match (mode, args.get(2)) {
+        (Some("--host"), Some(port)) => {
+            let port = port
+                .parse()
+                .map_err(|_| ggez::GameError::ConfigError(format!("`{}` is not a port", port)))?;
+            let netcode = match args.get(3).map(String::as_str) {
+                Some("--rollback") => Netcode::Rollback,
+                _ => Netcode::Lockstep,
+            };
+            let (link, seed) = Link::host(port, netcode)?;
+            Some((link, seed, netcode))
+        }
+        (Some("--connect"), Some(addr)) => {
+            let name = args.get(3).map_or("player", String::as_str);
+            let lobby = args.get(4).map_or("main", String::as_str);
+            Some(Link::connect(addr, name, lobby)?)
+        }
+        (Some("--host"), None) | (Some("--connect"), None) => {
+            return Err(ggez::GameError::ConfigError(
+                "--host needs a port and --connect the address of the host".to_string(),
+            ))
+        }
+        _ => None,
+    };
+    let latency = match (mode, args.get(2), args.get(3)) {
+        (Some("--versus"), Some(flag), Some(ms)) if flag == "--latency" => {
+            Some(Duration::from_millis(ms.parse().map_err(|_| {
+                ggez::GameError::ConfigError(format!("`{}` is not a latency in ms", ms))
+            })?))
+        }
+        _ => None,
+    };
+    let difficulty = match (mode, args.get(2), args.get(3)) {
+        (Some("--versus"), Some(flag), level) if flag == "--cpu" => {
+            let level = level.map_or("medium", String::as_str);
+            Some(Difficulty::from_name(level).ok_or_else(|| {
+                ggez::GameError::ConfigError(format!(
+                    "`{}` is not a difficulty, try easy, medium, hard or expert",
+                    level
+                ))
+            })?)
+        }
+        _ => None,
+    };
+    let bots = match (mode, args.get(2)) {
+        (Some("--royale"), Some(bots)) => match bots.parse() {
+            Ok(bots) if (royale::MIN_BOTS..=royale::MAX_BOTS).contains(&bots) => bots,
+            _ => {
+                return Err(ggez::GameError::ConfigError(format!(
+                    "--royale needs between {} and {} bots",
+                    royale::MIN_BOTS,
+                    royale::MAX_BOTS
+                )))
+            }
+        },
+        _ => 49,
+    };
+    // the bot is started before opening the window too, it may fail to
+    let tbp = match (mode, args.get(2)) {
+        (Some("--tbp"), Some(_)) => Some(TbpGame::new(&args[2..])?),
+        (Some("--tbp"), None) => {
+            return Err(ggez::GameError::ConfigError(
+                "--tbp needs the command that runs the bot".to_string(),
+            ))
+        }
+        _ => None,
+    };
+    // Here we use a ContextBuilder to setup metadata about our game. First the title and author
+    let (mut ctx, events_loop) = ggez::ContextBuilder::new("tetris", "Pepe Márquez")
+        .window_setup(
+            ggez::conf::WindowSetup::default()
+                .title("Tetris!")
+                .vsync(settings.video.vsync),
+        )
+        // Now we get to set the size of the window, from the settings, the game is drawn for
+        // SCREEN_SIZE and scaled to it
+        .window_mode(settings.video.window_mode())
+        .build()?;
+    graphics::set_screen_coordinates(
+        &mut ctx,
+        graphics::Rect::new(0.0, 0.0, SCREEN_SIZE.0, SCREEN_SIZE.1),
+    )?;
+
+    // And finally we actually run our game, passing in our context and state.
+    if let Some(tbp) = tbp {
+        return run(ctx, events_loop, tbp, settings, spectators);
+    }
+    if let Some(replay) = replay {
+        event::run(ctx, events_loop, Playback::new(replay));
+    }
+    match (mode, puzzle, link) {
+        (Some("--spectate"), _, _) => match args.get(2) {
+            Some(addr) => event::run(ctx, events_loop, Spectator::connect(addr)?),
+            None => Err(ggez::GameError::ConfigError(
+                "--spectate needs the address of the game".to_string(),
+            )),
+        },
+        (Some("--versus"), _, _) => match (difficulty, latency) {
+            (Some(difficulty), _) => run(
+                ctx,
+                events_loop,
+                Versus::against(difficulty),
+                settings,
+                spectators,
+            ),
+            (_, Some(latency)) => run(
+                ctx,
+                events_loop,
+                LoopbackVersus::new(latency),
+                settings,
+                spectators,
+            ),
+            (None, None) => run(ctx, events_loop, Versus::new(), settings, spectators),
+        },
+        (Some("--coop"), _, _) => run(ctx, events_loop, Coop::new(), settings, spectators),
+        (Some("--royale"), _, _) => run(ctx, events_loop, Royale::new(bots), settings, spectators),
+        (_, Some(puzzle), _) => {
+            let mut state = GameState::from_puzzle(puzzle);
+            state.bot_weights = weights;
+            state.bot_time = bot_time;
+            run(ctx, events_loop, state, settings, spectators)
+        }
+        (_, _, Some((link, seed, Netcode::Lockstep))) => run(
+            ctx,
+            events_loop,
+            NetVersus::new(link, seed),
+            settings,
+            spectators,
+        ),
+        (_, _, Some((link, seed, Netcode::Rollback))) => run(
+            ctx,
+            events_loop,
+            RollbackVersus::new(link, seed),
+            settings,
+            spectators,
+        ),
+        _ => {
+            let mut state = GameState::recorded();
+            state.bot_weights = weights;
+            state.bot_time = bot_time;
+            run(ctx, events_loop, state, settings, spectators)
+        }
+    }
+}
+
+impl Configurable for GameState {
+    fn configure(&mut self, settings: &Settings) {
+        self.keys = settings.controls.bindings();
+        self.handling = settings.handling;
+        self.held = Held::default();
+        // new modifiers start a new single player game, the ones picked with
+        // the keys stay until then
+        if self.recorder.is_some() && settings.gameplay != self.gameplay {
+            self.gameplay = settings.gameplay;
+            self.grid = settings.gameplay.grid();
+            self.visibility = settings.gameplay.visibility;
+            self.new_game();
+        }
+    }
+}
+
+/// Runs `handler` with the settings, streaming its boards when there's a port
+/// for spectators.
+fn run<H>(
+    ctx: Context,
+    events_loop: event::EventLoop<()>,
+    handler: H,
+    settings: Settings,
+    spectators: Option<u16>,
+) -> GameResult
+where
+    H: event::EventHandler<ggez::GameError> + Watched + Configurable + 'static,
+{
+    let handler = Configured::new(handler, settings);
+    match spectators {
+        Some(port) => event::run(ctx, events_loop, Broadcast::new(handler, port)?),
+        None => event::run(ctx, events_loop, handler),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orients_blocks_on_the_grid() {
+        let block = Block::new(2, 3, Color::WHITE);
+        let at = |grid: Grid| {
+            let oriented = grid.orient(block);
+            (oriented.x, oriented.y)
+        };
+        let grid = Grid::normal();
+        assert_eq!(at(grid), (2, 3));
+        let mirrored = Grid {
+            mirrored: true,
+            ..grid
+        };
+        assert_eq!(at(mirrored), (7, 3));
+        let upside_down = Grid {
+            upside_down: true,
+            ..grid
+        };
+        assert_eq!(at(upside_down), (2, 16));
+        let both = Grid {
+            mirrored: true,
+            upside_down: true,
+            ..Grid::big()
+        };
+        assert_eq!(at(both), (2, 6));
+        for grid in [grid, mirrored, upside_down, both] {
+            assert_eq!(grid.orient(grid.orient(block)), block);
+        }
+    }
+
+    #[test]
+    fn tells_mini_tspins_apart() {
+        // a T pointing up with its center at (5, 1)
+        let fig = Figure::new(0);
+        let kind = |corners: &[(i16, i16)]| tspin_kind(&fig, |x, y| corners.contains(&(x, y)));
+        assert_eq!(kind(&[(4, 2), (6, 2)]), (false, false));
+        assert_eq!(kind(&[(4, 2), (6, 2), (4, 0)]), (true, true));
+        assert_eq!(kind(&[(4, 2), (4, 0), (6, 0)]), (true, false));
+        assert_eq!(kind(&[(4, 2), (6, 2), (4, 0), (6, 0)]), (true, false));
+        assert_eq!(tspin_kind(&Figure::new(1), |_, _| true), (false, false));
+    }
+
+    #[test]
+    fn occupancy_follows_the_stack() {
+        let mut board = GameState::with_seed(11);
+        let inputs = [Input::Left, Input::Rotate, Input::Right, Input::Right];
+        for frame in 0..3000 {
+            if frame % 400 == 0 {
+                board.garbage.receive(2, board.ticks);
+            }
+            board.handle_input(inputs[frame % 7 % 4]);
+            board.handle_input(Input::SoftDrop);
+            board.tick();
+            assert_eq!(
+                board.occupancy,
+                Occupancy::of(&board.static_blocks, board.grid)
+            );
+            if board.gameover {
+                board.restart();
+            }
+        }
+        assert!(board.score > 0 || board.seats[0].pieces > 50);
+    }
+
+    #[test]
+    fn gravity_follows_the_grid() {
+        let upside_down = Grid {
+            upside_down: true,
+            ..Grid::normal()
+        };
+        assert_eq!(upside_down.fall(), -1);
+        assert_eq!(upside_down.floor_row(), 0);
+        assert_eq!(upside_down.spawn_row(), GRID_HEIGHT - 1);
+        assert!(upside_down.ilegal_coords(0, -1));
+        assert!(!upside_down.ilegal_coords(0, GRID_HEIGHT));
+        // a figure spawns at the far side of the floor
+        let fig = Figure::new(1).spawned_on(upside_down);
+        assert!(fig.blocks.iter().all(|block| block.y >= GRID_HEIGHT - 2));
+    }
+
+    #[test]
+    fn levels_speed_up_the_fall() {
+        let mut state = GameState::with_seed(0);
+        let falls = |state: &mut GameState, score: u32| {
+            state.score = score;
+            state.tick();
+            let top = |state: &GameState| state.seats[0].actual_figure.unwrap().blocks[0].y;
+            // from one row down to the next
+            let fall = |state: &mut GameState| {
+                let (start, mut frames) = (top(state), 0);
+                while top(state) == start {
+                    state.tick();
+                    frames += 1;
+                }
+                frames
+            };
+            fall(state);
+            (state.level(), fall(state))
+        };
+        assert_eq!(falls(&mut state, 0), (1, 60));
+        assert_eq!(falls(&mut state, 19), (2, 55));
+        assert_eq!(falls(&mut state, 100), (11, 10));
+        assert_eq!(falls(&mut state, 500), (51, 10));
+    }
+
+    fn moved(mut fig: Figure, x: i16, y: i16) -> Figure {
+        for block in fig.blocks.iter_mut() {
+            block.x += x;
+            block.y += y;
+        }
+        fig
+    }
+
+    #[test]
+    fn coop_seats_spawn_over_their_half() {
+        let state = GameState::coop(3);
+        assert_eq!(state.grid.width, 2 * GRID_WIDTH);
+        let half = GRID_WIDTH;
+        for number in 0..7 {
+            let left = state.seats[0].spawned(Figure::new(number), state.grid);
+            let right = state.seats[1].spawned(Figure::new(number), state.grid);
+            assert!(left.blocks.iter().all(|block| (0..half).contains(&block.x)));
+            assert!(right
+                .blocks
+                .iter()
+                .all(|block| (half..2 * half).contains(&block.x)));
+            // the same place on each half
+            assert_eq!(moved(left, half, 0), right);
+        }
+    }
+
+    #[test]
+    fn coop_figures_dont_move_into_each_other() {
+        let mut state = GameState::coop(3);
+        let square = state.seats[0].spawned(Figure::new(1), state.grid);
+        state.seats[0].actual_figure = Some(square);
+        // right next to it, one more step to the right would overlap
+        state.seats[1].actual_figure = Some(moved(square, 2, 0));
+        state.handle_seat_input(0, Input::Right);
+        assert_eq!(state.seats[0].actual_figure, Some(square));
+        state.handle_seat_input(1, Input::Left);
+        assert_eq!(state.seats[1].actual_figure, Some(moved(square, 2, 0)));
+        state.handle_seat_input(0, Input::Left);
+        assert_eq!(state.seats[0].actual_figure, Some(moved(square, -1, 0)));
+        // under the other figure
+        state.seats[1].actual_figure = Some(moved(square, -1, 2));
+        state.handle_seat_input(0, Input::SoftDrop);
+        assert_eq!(state.seats[0].actual_figure, Some(moved(square, -1, 0)));
+    }
+
+    #[test]
+    fn coop_holds_only_when_the_coming_figure_fits() {
+        let mut state = GameState::coop(3);
+        let grid = state.grid;
+        let fig = moved(state.seats[0].spawned(Figure::new(2), grid), 0, 10);
+        state.seats[0].actual_figure = Some(fig);
+        let coming = state.seats[0].next_figures[0];
+        // the partner's figure sits where the coming one would spawn
+        state.seats[1].actual_figure = Some(state.seats[0].spawned(coming, grid));
+        state.handle_seat_input(0, Input::Hold);
+        assert_eq!(state.seats[0].actual_figure, Some(fig));
+        assert_eq!(state.seats[0].keep_figure, None);
+        state.seats[1].actual_figure = Some(moved(state.seats[1].spawned(coming, grid), 0, 10));
+        state.handle_seat_input(0, Input::Hold);
+        assert_eq!(
+            state.seats[0].actual_figure,
+            Some(state.seats[0].spawned(coming, grid))
+        );
+        assert_eq!(state.seats[0].keep_figure, Some(Figure::new(2)));
+    }
+}
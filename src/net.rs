@@ -0,0 +1,575 @@
+//! Versus over the network. Both games run the two boards in lockstep from the
+//! same seed: every frame each player sends their inputs and the garbage their
+//! board sent, and waits for the other player's before moving on.
+//!
+//! The protocol is made of text lines over TCP. The player connecting starts
+//! with `hello <name> <lobby>`, the host answers `tetris <version> <seed>
+//! <netcode>`, the player connecting answers `tetris <version>` and then, with
+//! the `lockstep` netcode, both send one line per frame:
+//!
+//! ```text
+//! frame <n> <inputs> <garbage> [restart]
+//! ```
+//!
+//! where `<inputs>` are the letters of the inputs of that frame (`-` for none),
+//! `<garbage>` the lines sent to the other board on that frame and `restart`
+//! asks for a rematch once the match is over. The garbage is also computed by
+//! the other game, if they don't agree the games went out of sync. The
+//! `rollback` netcode is described in `src/rollback.rs`.
+//!
+//! When a match ends each game also sends `result <frame> <won|lost>`, with the
+//! frame the loser topped out. These are for `tetris-server`, which stands in
+//! for the host and pairs the players of each lobby, games skip them.
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use ggez::event::{self, Button, GamepadId, KeyCode, KeyMods};
+use ggez::graphics;
+use ggez::{timer, Context, GameError, GameResult};
+
+use crate::input::{Held, Input, KeyBindings};
+use crate::settings::{Configurable, Handling, Settings};
+use crate::spectate::Watched;
+use crate::versus::draw_banner;
+use crate::{random_seed, GameState, Layout, FPS};
+
+/// The version both ends greet each other with, `tetris-server` speaks the
+/// same protocol.
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// How the two games keep in sync, the host picks it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Netcode {
+    Lockstep,
+    Rollback,
+}
+impl Netcode {
+    fn name(&self) -> &'static str {
+        match *self {
+            Netcode::Lockstep => "lockstep",
+            Netcode::Rollback => "rollback",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Netcode> {
+        match name {
+            "lockstep" => Some(Netcode::Lockstep),
+            "rollback" => Some(Netcode::Rollback),
+            _ => None,
+        }
+    }
+}
+
+/// What one player did on one frame.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Frame {
+    pub inputs: Vec<Input>,
+    // lines of garbage their board sent on this frame
+    pub garbage: u32,
+    // they want a rematch
+    pub restart: bool,
+}
+impl Frame {
+    /// The line sent for frame `n`.
+    pub fn encode(&self, n: u64) -> String {
+        let restart = if self.restart { " restart" } else { "" };
+        format!(
+            "frame {} {} {}{}",
+            n,
+            encode_inputs(&self.inputs),
+            self.garbage,
+            restart
+        )
+    }
+
+    /// Reads the line of frame `n`, lines only ever come in order.
+    pub fn decode(line: &str, n: u64) -> Result<Frame, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["frame", frame, inputs, garbage, ref rest @ ..] => {
+                if frame.parse::<u64>() != Ok(n) {
+                    return Err(format!("expected frame {}, got `{}`", n, line));
+                }
+                let inputs = decode_inputs(inputs)?;
+                let garbage = garbage
+                    .parse()
+                    .map_err(|_| format!("`{}` is not a line count", garbage))?;
+                let restart = match rest {
+                    [] => false,
+                    ["restart"] => true,
+                    _ => return Err(format!("unexpected `{}`", rest.join(" "))),
+                };
+                Ok(Frame {
+                    inputs,
+                    garbage,
+                    restart,
+                })
+            }
+            _ => Err(format!("expected frame {}, got `{}`", n, line)),
+        }
+    }
+}
+
+/// The letters of `inputs`, `-` when there are none.
+pub fn encode_inputs(inputs: &[Input]) -> String {
+    if inputs.is_empty() {
+        "-".to_string()
+    } else {
+        inputs.iter().map(Input::letter).collect()
+    }
+}
+
+pub fn decode_inputs(letters: &str) -> Result<Vec<Input>, String> {
+    if letters == "-" {
+        return Ok(vec![]);
+    }
+    letters
+        .chars()
+        .map(|letter| Input::from_letter(letter).ok_or(format!("unknown input `{}`", letter)))
+        .collect()
+}
+
+/// How lines get to the other game, a socket or a simulated one.
+pub trait Transport {
+    fn send(&mut self, line: &str) -> GameResult;
+    /// The next line if one already arrived, this never waits.
+    fn try_receive(&mut self) -> GameResult<Option<String>>;
+}
+
+/// The connection to the other player. The socket always blocks, the lines
+/// come in on a thread of their own so `try_receive` doesn't wait for them.
+pub struct Link {
+    lines: Receiver<String>,
+    writer: TcpStream,
+}
+impl Link {
+    /// Waits for the other player on `port`, returns the link and the seed of
+    /// the match.
+    pub fn host(port: u16, netcode: Netcode) -> GameResult<(Link, u64)> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        println!("Waiting for the other player on port {}", port);
+        Link::accept(&listener, netcode)
+    }
+
+    /// Waits for the other player on `listener`, see `host`.
+    pub fn accept(listener: &TcpListener, netcode: Netcode) -> GameResult<(Link, u64)> {
+        let (stream, _) = listener.accept()?;
+        let mut link = Link::new(stream)?;
+        // there's only one lobby here, whichever the other player asked for
+        let line = link.receive()?;
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["hello", name, _lobby] => println!("{} joined", name),
+            _ => return Err(protocol_error(format!("unexpected greeting `{}`", line))),
+        }
+        let seed = random_seed();
+        link.send(&format!(
+            "tetris {} {} {}",
+            PROTOCOL_VERSION,
+            seed,
+            netcode.name()
+        ))?;
+        let line = link.receive()?;
+        if line != format!("tetris {}", PROTOCOL_VERSION) {
+            return Err(protocol_error(format!("unexpected greeting `{}`", line)));
+        }
+        Ok((link, seed))
+    }
+
+    /// Joins the game hosted at `addr`, or waits for another player in `lobby`
+    /// when it's a server. Returns the link, the seed of the match and the
+    /// netcode the host picked.
+    pub fn connect(addr: &str, name: &str, lobby: &str) -> GameResult<(Link, u64, Netcode)> {
+        let mut link = Link::new(TcpStream::connect(addr)?)?;
+        link.send(&format!("hello {} {}", name, lobby))?;
+        let line = link.receive()?;
+        let (seed, netcode) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["tetris", version, seed, netcode] if version == PROTOCOL_VERSION.to_string() => (
+                seed.parse()
+                    .map_err(|_| protocol_error(format!("`{}` is not a seed", seed)))?,
+                Netcode::from_name(netcode)
+                    .ok_or_else(|| protocol_error(format!("unknown netcode `{}`", netcode)))?,
+            ),
+            _ => return Err(protocol_error(format!("unexpected greeting `{}`", line))),
+        };
+        link.send(&format!("tetris {}", PROTOCOL_VERSION))?;
+        Ok((link, seed, netcode))
+    }
+
+    fn new(stream: TcpStream) -> GameResult<Link> {
+        // every frame waits for the other player, don't let their inputs wait
+        // for more data to fill a packet
+        stream.set_nodelay(true)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in reader.lines().map_while(Result::ok) {
+                if sender.send(line.trim().to_string()).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Link {
+            lines,
+            writer: stream,
+        })
+    }
+
+    /// Waits for the next line, skipping the results.
+    fn receive(&mut self) -> GameResult<String> {
+        loop {
+            let line = self
+                .lines
+                .recv()
+                .map_err(|_| protocol_error("the other player left".to_string()))?;
+            if !is_result(&line) {
+                return Ok(line);
+            }
+        }
+    }
+}
+
+impl Transport for Link {
+    fn send(&mut self, line: &str) -> GameResult {
+        writeln!(self.writer, "{}", line)?;
+        Ok(())
+    }
+
+    fn try_receive(&mut self) -> GameResult<Option<String>> {
+        match self.lines.try_recv() {
+            Ok(line) => Ok(Some(line)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => {
+                Err(protocol_error("the other player left".to_string()))
+            }
+        }
+    }
+}
+
+pub fn is_result(line: &str) -> bool {
+    line.starts_with("result ")
+}
+
+/// The line a game sends once the match on `boards` ended on `frame`.
+pub fn result_line(boards: &[GameState; 2], frame: u64) -> Option<String> {
+    winner(boards).map(|winner| {
+        let result = if winner == 0 { "won" } else { "lost" };
+        format!("result {} {}", frame, result)
+    })
+}
+
+pub fn protocol_error(message: String) -> GameError {
+    GameError::CustomError(message)
+}
+
+/// Once a player tops out the other one wins.
+pub fn winner(boards: &[GameState; 2]) -> Option<usize> {
+    boards
+        .iter()
+        .position(|board| board.gameover)
+        .map(|loser| 1 - loser)
+}
+
+/// Both boards of a network match, 0 is the one of this player.
+pub struct Lockstep {
+    pub boards: [GameState; 2],
+    link: Link,
+    // the frame about to be played
+    frame: u64,
+}
+impl Lockstep {
+    pub fn new(link: Link, seed: u64) -> Self {
+        Lockstep {
+            boards: [GameState::with_seed(seed), GameState::with_seed(seed)],
+            link,
+            frame: 0,
+        }
+    }
+
+    /// Plays one frame of both boards with what this player did, waiting for
+    /// what the other one did on the same frame.
+    pub fn step(&mut self, mut local: Frame) -> GameResult {
+        let over = winner(&self.boards).is_some();
+        if !over {
+            play(&mut self.boards[0], &local);
+        }
+        local.garbage = std::mem::take(&mut self.boards[0].garbage.outgoing);
+        self.link.send(&local.encode(self.frame))?;
+        let line = self.link.receive()?;
+        let remote = Frame::decode(&line, self.frame).map_err(protocol_error)?;
+        if !over {
+            play(&mut self.boards[1], &remote);
+        }
+        if std::mem::take(&mut self.boards[1].garbage.outgoing) != remote.garbage {
+            return Err(protocol_error(format!(
+                "the games went out of sync on frame {}",
+                self.frame
+            )));
+        }
+        let ticks = self.boards[0].ticks;
+        self.boards[0].garbage.receive(remote.garbage, ticks);
+        let ticks = self.boards[1].ticks;
+        self.boards[1].garbage.receive(local.garbage, ticks);
+        if over && (local.restart || remote.restart) {
+            for board in self.boards.iter_mut() {
+                board.restart();
+            }
+        }
+        if let (false, Some(line)) = (over, result_line(&self.boards, self.frame)) {
+            self.link.send(&line)?;
+        }
+        self.frame += 1;
+        Ok(())
+    }
+}
+
+pub fn play(board: &mut GameState, frame: &Frame) {
+    for input in frame.inputs.iter() {
+        board.handle_input(*input);
+    }
+    board.tick();
+}
+
+/// A network match on screen, this player's board on the left.
+pub struct NetVersus {
+    game: Lockstep,
+    // what this player did since the last frame
+    pending: Frame,
+    keys: PlayerKeys,
+}
+impl NetVersus {
+    pub fn new(link: Link, seed: u64) -> Self {
+        NetVersus {
+            game: Lockstep::new(link, seed),
+            pending: Frame::default(),
+            keys: PlayerKeys::default(),
+        }
+    }
+}
+
+impl Configurable for NetVersus {
+    fn configure(&mut self, settings: &Settings) {
+        self.keys.configure(settings);
+    }
+}
+
+/// The keys of the player of a network match and how they repeat when held,
+/// from the settings.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PlayerKeys {
+    bindings: KeyBindings,
+    handling: Handling,
+    held: Held,
+}
+impl Default for PlayerKeys {
+    fn default() -> Self {
+        PlayerKeys {
+            bindings: KeyBindings::arrows(),
+            handling: Handling::default(),
+            held: Held::default(),
+        }
+    }
+}
+impl PlayerKeys {
+    pub fn configure(&mut self, settings: &Settings) {
+        *self = PlayerKeys {
+            bindings: settings.controls.bindings(),
+            handling: settings.handling,
+            held: Held::default(),
+        };
+    }
+
+    /// Adds the input of `keycode` to `frame`, the repeats of the system are
+    /// left out for the ones of the settings.
+    pub fn press(&mut self, keycode: KeyCode, repeat: bool, frame: &mut Frame) {
+        if let (Some(input), false) = (self.bindings.input(keycode), repeat) {
+            frame.inputs.push(input);
+            self.held.press(input);
+        }
+    }
+
+    pub fn release(&mut self, keycode: KeyCode) {
+        if let Some(input) = self.bindings.input(keycode) {
+            self.held.release(input);
+        }
+    }
+
+    /// Adds the repeats of the key held to `frame`, played on `board`.
+    pub fn repeat(&mut self, frame: &mut Frame, board: &GameState) {
+        let repeats = self.held.repeats(&self.handling, board.grid.width);
+        frame.inputs.extend(repeats);
+    }
+}
+
+impl Watched for NetVersus {
+    fn boards(&self) -> Vec<&GameState> {
+        self.game.boards.iter().collect()
+    }
+}
+
+impl event::EventHandler<ggez::GameError> for NetVersus {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        while timer::check_update_time(ctx, FPS) {
+            self.keys.repeat(&mut self.pending, &self.game.boards[0]);
+            self.game.step(std::mem::take(&mut self.pending))?;
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
+        draw_match(ctx, &self.game.boards)?;
+        graphics::present(ctx)?;
+        timer::yield_now();
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        repeat: bool,
+    ) {
+        // there's no pause, the other player would be left waiting
+        if keycode == KeyCode::R {
+            self.pending.restart = winner(&self.game.boards).is_some();
+        } else {
+            self.keys.press(keycode, repeat, &mut self.pending);
+        }
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
+        self.keys.release(keycode);
+    }
+
+    fn gamepad_button_down_event(&mut self, _ctx: &mut Context, btn: Button, _id: GamepadId) {
+        if let Some(input) = Input::from_button(btn) {
+            self.pending.inputs.push(input);
+        }
+    }
+}
+
+/// Draws both boards of a network match, this player's on the left, and who
+/// won once it's over.
+pub fn draw_match(ctx: &mut Context, boards: &[GameState; 2]) -> GameResult {
+    for (n, board) in boards.iter().enumerate() {
+        board.draw_board(ctx, &Layout::versus(n))?;
+    }
+    match winner(boards) {
+        Some(0) => draw_banner(ctx, "You win !  press R for a rematch"),
+        Some(_) => draw_banner(ctx, "You lose :(  press R for a rematch"),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn frames_round_trip() {
+        let frames = [
+            Frame::default(),
+            Frame {
+                inputs: vec![Input::Left, Input::Rotate, Input::SoftDrop],
+                garbage: 4,
+                restart: false,
+            },
+            Frame {
+                inputs: vec![Input::Hold],
+                garbage: 0,
+                restart: true,
+            },
+        ];
+        for (n, frame) in frames.iter().enumerate() {
+            let line = frame.encode(n as u64 + 10);
+            assert_eq!(Frame::decode(&line, n as u64 + 10), Ok(frame.clone()));
+        }
+        assert_eq!(Frame::default().encode(3), "frame 3 - 0");
+        assert!(Frame::decode("frame 4 - 0", 3).is_err());
+        assert!(Frame::decode("frame 3 Q 0", 3).is_err());
+        assert!(Frame::decode("frame 3 - many", 3).is_err());
+        assert!(Frame::decode("frame 3 - 0 rematch", 3).is_err());
+        assert!(Frame::decode("result 3 won", 3).is_err());
+    }
+
+    // what player `n` presses on `frame` of the tests
+    fn script(n: u64, frame: u64) -> Frame {
+        let inputs = match (frame * 7 + n * 3) % 13 {
+            0 => vec![Input::Left],
+            1 => vec![Input::Right],
+            2 => vec![Input::Rotate],
+            3 | 4 => vec![Input::SoftDrop],
+            5 => vec![Input::Hold],
+            _ => vec![],
+        };
+        Frame {
+            inputs,
+            ..Frame::default()
+        }
+    }
+
+    fn listen() -> (TcpListener, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        (listener, addr)
+    }
+
+    #[test]
+    fn lockstep_keeps_both_games_in_sync() {
+        const FRAMES: u64 = 600;
+        let (listener, addr) = listen();
+        let host = thread::spawn(move || {
+            let (link, seed) = Link::accept(&listener, Netcode::Lockstep).unwrap();
+            let mut game = Lockstep::new(link, seed);
+            for frame in 0..FRAMES {
+                game.step(script(0, frame)).unwrap();
+            }
+            (seed, game.boards)
+        });
+        let (link, seed, netcode) = Link::connect(&addr, "guest", "main").unwrap();
+        assert_eq!(netcode, Netcode::Lockstep);
+        let mut game = Lockstep::new(link, seed);
+        for frame in 0..FRAMES {
+            game.step(script(1, frame)).unwrap();
+        }
+        let (host_seed, host_boards) = host.join().unwrap();
+        assert_eq!(host_seed, seed);
+        assert_eq!(host_boards[0], game.boards[1]);
+        assert_eq!(host_boards[1], game.boards[0]);
+        // and both are the games the scripts play on their own
+        let mut board = GameState::with_seed(seed);
+        for frame in 0..FRAMES {
+            play(&mut board, &script(1, frame));
+        }
+        assert_eq!(board.static_blocks, game.boards[0].static_blocks);
+        assert!(board.seats[0].pieces > 1);
+    }
+
+    #[test]
+    fn lockstep_catches_a_desync() {
+        let (listener, addr) = listen();
+        let host = thread::spawn(move || {
+            let (link, seed) = Link::accept(&listener, Netcode::Lockstep).unwrap();
+            let mut game = Lockstep::new(link, seed);
+            game.step(Frame::default())
+        });
+        let (mut link, _, _) = Link::connect(&addr, "guest", "main").unwrap();
+        // the guest claims garbage its board can't have sent
+        let frame = Frame {
+            garbage: 3,
+            ..Frame::default()
+        };
+        link.send(&frame.encode(0)).unwrap();
+        let error = host.join().unwrap().unwrap_err();
+        assert!(
+            error.to_string().contains("out of sync on frame 0"),
+            "{}",
+            error
+        );
+    }
+}
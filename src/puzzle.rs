@@ -0,0 +1,248 @@
+//! Puzzle mode: a starting stack, a fixed queue of pieces and a goal to meet
+//! before the pieces run out.
+//!
+//! Puzzles are plain text files like this one:
+//!
+//! ```text
+//! # comments start with a hash
+//! name: Perfect clear
+//! goal: perfect-clear
+//! pieces: O O
+//! board:
+//! XXXXXX....
+//! XXXXXX....
+//! ```
+//!
+//! The goal is one of `lines <n>`, `perfect-clear` or `tspin <n>` (a T-spin
+//! clearing `n` lines). The board rows sit on the floor of the playfield, a
+//! `.` is an empty cell, `X` a garbage block and a piece letter a block with
+//! that piece's color.
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use ggez::{GameError, GameResult};
+
+use crate::{Block, Figure, PieceType, GARBAGE_COLOR, GRID_HEIGHT, GRID_WIDTH};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Goal {
+    ClearLines(u32),
+    PerfectClear,
+    TSpin(u32),
+}
+impl Goal {
+    /// Checks the goal right after a piece locks. `lines` is the total of lines
+    /// cleared in the puzzle and `cleared` the ones cleared by this piece.
+    pub fn is_met(&self, lines: u32, cleared: u32, tspin: bool, board_empty: bool) -> bool {
+        match *self {
+            Goal::ClearLines(n) => lines >= n,
+            Goal::PerfectClear => cleared > 0 && board_empty,
+            Goal::TSpin(n) => tspin && cleared >= n,
+        }
+    }
+}
+impl fmt::Display for Goal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Goal::ClearLines(1) => write!(f, "Clear 1 line"),
+            Goal::ClearLines(n) => write!(f, "Clear {} lines", n),
+            Goal::PerfectClear => write!(f, "Perfect clear"),
+            Goal::TSpin(1) => write!(f, "T-spin single"),
+            Goal::TSpin(2) => write!(f, "T-spin double"),
+            Goal::TSpin(3) => write!(f, "T-spin triple"),
+            Goal::TSpin(n) => write!(f, "T-spin clearing {} lines", n),
+        }
+    }
+}
+
+/// How far the player is in the puzzle.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Outcome {
+    Playing,
+    Solved,
+    Failed,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Puzzle {
+    pub name: String,
+    pub goal: Goal,
+    // blocks the board starts with
+    pub stack: Vec<Block>,
+    pub pieces: Vec<PieceType>,
+}
+impl Puzzle {
+    pub fn load<P: AsRef<Path>>(path: P) -> GameResult<Puzzle> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        Puzzle::parse(&text)
+            .map_err(|e| GameError::ResourceLoadError(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn parse(text: &str) -> Result<Puzzle, String> {
+        let mut name = String::from("Puzzle");
+        let mut goal = None;
+        let mut pieces = vec![];
+        let mut rows: Vec<&str> = vec![];
+        let mut in_board = false;
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if in_board {
+                rows.push(line);
+                continue;
+            }
+            let (key, value) = line
+                .split_once(':')
+                .ok_or(format!("line {}: expected `key: value`", n + 1))?;
+            let value = value.trim();
+            match key.trim() {
+                "name" => name = value.to_string(),
+                "goal" => {
+                    goal = Some(parse_goal(value).map_err(|e| format!("line {}: {}", n + 1, e))?)
+                }
+                "pieces" => {
+                    for letter in value.split_whitespace() {
+                        let piece = letter
+                            .chars()
+                            .next()
+                            .and_then(PieceType::from_letter)
+                            .filter(|_| letter.len() == 1)
+                            .ok_or(format!("line {}: unknown piece `{}`", n + 1, letter))?;
+                        pieces.push(piece);
+                    }
+                }
+                "board" => in_board = true,
+                other => return Err(format!("line {}: unknown key `{}`", n + 1, other)),
+            }
+        }
+        let goal = goal.ok_or("the puzzle has no goal")?;
+        if pieces.is_empty() {
+            return Err("the puzzle has no pieces".to_string());
+        }
+        // the top row is where pieces spawn, a stack touching it would be a game over
+        if rows.len() >= GRID_HEIGHT as usize {
+            return Err(format!(
+                "the board can have at most {} rows",
+                GRID_HEIGHT - 1
+            ));
+        }
+        let mut stack = vec![];
+        let first_row = GRID_HEIGHT - rows.len() as i16;
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != GRID_WIDTH as usize {
+                return Err(format!(
+                    "board row `{}` must be {} cells wide",
+                    row, GRID_WIDTH
+                ));
+            }
+            for (x, cell) in row.chars().enumerate() {
+                let color = match cell {
+                    '.' => continue,
+                    'X' => GARBAGE_COLOR,
+                    letter => match PieceType::from_letter(letter) {
+                        Some(piece) => Figure::new(piece.number()).blocks[0].color,
+                        None => return Err(format!("unknown board cell `{}`", letter)),
+                    },
+                };
+                stack.push(Block::new(x as i16, first_row + y as i16, color));
+            }
+        }
+        Ok(Puzzle {
+            name,
+            goal,
+            stack,
+            pieces,
+        })
+    }
+}
+
+fn parse_goal(value: &str) -> Result<Goal, String> {
+    let mut words = value.split_whitespace();
+    let kind = words.next().unwrap_or_default();
+    let count = words.next().map(|n| {
+        n.parse::<u32>()
+            .ok()
+            .filter(|n| *n > 0)
+            .ok_or(format!("`{}` is not a line count", n))
+    });
+    match (kind, count) {
+        ("lines", Some(n)) => Ok(Goal::ClearLines(n?)),
+        ("perfect-clear", None) => Ok(Goal::PerfectClear),
+        ("tspin", Some(n)) => Ok(Goal::TSpin(n?)),
+        _ => Err(format!("unknown goal `{}`", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_puzzle() {
+        let puzzle = Puzzle::parse(
+            "# a comment\nname: Two lines\ngoal: lines 2\npieces: I o\nboard:\nXXXXXXXXX.\nTTTTTTTTT.\n",
+        )
+        .unwrap();
+        assert_eq!(puzzle.name, "Two lines");
+        assert_eq!(puzzle.goal, Goal::ClearLines(2));
+        assert_eq!(puzzle.pieces, vec![PieceType::Stick, PieceType::Square]);
+        assert_eq!(puzzle.stack.len(), 18);
+        // the rows sit on the floor
+        assert!(puzzle.stack[..9]
+            .iter()
+            .all(|block| block.y == GRID_HEIGHT - 2));
+        assert!(puzzle.stack[9..]
+            .iter()
+            .all(|block| block.y == GRID_HEIGHT - 1));
+        assert_eq!(puzzle.stack[0].color, GARBAGE_COLOR);
+        assert_eq!(puzzle.stack[9].color, Figure::new(0).blocks[0].color);
+    }
+
+    #[test]
+    fn loads_the_puzzles_of_the_repo() {
+        for entry in fs::read_dir("puzzles").unwrap() {
+            let path = entry.unwrap().path();
+            assert!(Puzzle::load(&path).is_ok(), "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn parses_the_goals() {
+        assert_eq!(parse_goal("perfect-clear"), Ok(Goal::PerfectClear));
+        assert_eq!(parse_goal("tspin 2"), Ok(Goal::TSpin(2)));
+        assert!(parse_goal("lines 0").is_err());
+        assert!(parse_goal("lines").is_err());
+        assert!(parse_goal("score 3").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_puzzles() {
+        let errors = [
+            ("pieces: I\n", "the puzzle has no goal"),
+            ("goal: lines 1\n", "the puzzle has no pieces"),
+            ("goal: lines 1\npieces: Q\n", "line 2: unknown piece `Q`"),
+            ("goal: lines 1\nsize: 3\n", "line 2: unknown key `size`"),
+            ("goal lines 1\n", "line 1: expected `key: value`"),
+            (
+                "goal: lines 1\npieces: I\nboard:\nXXX\n",
+                "board row `XXX` must be 10 cells wide",
+            ),
+            (
+                "goal: lines 1\npieces: I\nboard:\nXXXXXXXXX?\n",
+                "unknown board cell `?`",
+            ),
+        ];
+        for (text, error) in errors {
+            assert_eq!(Puzzle::parse(text), Err(error.to_string()), "{}", text);
+        }
+        let tall = format!(
+            "goal: lines 1\npieces: I\nboard:\n{}",
+            "X.........\n".repeat(20)
+        );
+        assert!(Puzzle::parse(&tall).is_err());
+    }
+}
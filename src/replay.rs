@@ -0,0 +1,560 @@
+//! Replays of the single player game. The figures and the garbage holes only
+//! depend on the seed, so the seed, the grid and every input with the frame it
+//! came on are all it takes to play a game again. A replay is written to
+//! `replays/` when the game is over, or when it's quit or started again
+//! before that.
+//!
+//! The files are JSON, with the events as pairs of a frame and a letter: the
+//! letters of the inputs in the network protocol, `P` for a pause and `I` for
+//! the visibility of the stack changing. An event on frame `n` came after `n`
+//! frames of the game, the ones on the last frame came after the game's last
+//! frame, right before it was quit.
+//!
+//! ```text
+//! {"seed":1234,"grid":{"big":false,"mirrored":false,"upside_down":false},
+//!  "visibility":"visible","score":4,"frames":2210,"gameover":true,
+//!  "events":[[0,"L"],[37,"U"],...]}
+//! ```
+//!
+//! `tetris --replay <file>` plays a replay back: space pauses it, `.` and `,`
+//! step a frame forward and back, the up and down arrows change the speed from
+//! a quarter to four times and the left and right arrows seek five seconds
+//! back and forth. Seeking starts from the snapshot of the game taken every
+//! few seconds of the replay right before the frame sought, so it only plays
+//! a few seconds again.
+//!
+//! `tetris verify <file>` plays a replay again with no window and prints how
+//! the game ended, failing when that's not what the replay claims, so the
+//! scores sent with replays can be checked. `--verify` does the same.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ggez::event::{self, KeyCode, KeyMods};
+use ggez::graphics::{self, DrawParam, Font, PxScale, Text};
+use ggez::{timer, Context, GameError, GameResult};
+use mint::Point2;
+use serde::{Deserialize, Serialize};
+
+use crate::input::Input;
+use crate::{GameState, Grid, Layout, StackVisibility, FPS, SCREEN_SIZE};
+
+const REPLAYS_DIR: &str = "replays";
+// The playback speeds, the replay starts at the normal one.
+const SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+const NORMAL_SPEED: usize = 2;
+// Frames between two snapshots, and frames a seek skips.
+const SNAPSHOT_FRAMES: u64 = 5 * FPS as u64;
+const SEEK_FRAMES: u64 = 5 * FPS as u64;
+
+/// Something the player did to the game.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(into = "char", try_from = "char")]
+pub enum Event {
+    Input(Input),
+    Pause,
+    Visibility,
+}
+impl From<Event> for char {
+    fn from(event: Event) -> char {
+        match event {
+            Event::Input(input) => input.letter(),
+            Event::Pause => 'P',
+            Event::Visibility => 'I',
+        }
+    }
+}
+impl TryFrom<char> for Event {
+    type Error = String;
+
+    fn try_from(letter: char) -> Result<Self, Self::Error> {
+        match letter {
+            'P' => Ok(Event::Pause),
+            'I' => Ok(Event::Visibility),
+            _ => Input::from_letter(letter)
+                .map(Event::Input)
+                .ok_or_else(|| format!("`{}` is not an event", letter)),
+        }
+    }
+}
+
+/// The modifiers of the grid the game was played on.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GridSettings {
+    pub big: bool,
+    pub mirrored: bool,
+    pub upside_down: bool,
+}
+impl GridSettings {
+    pub fn of(grid: Grid) -> Self {
+        GridSettings {
+            big: grid.is_big(),
+            mirrored: grid.mirrored,
+            upside_down: grid.upside_down,
+        }
+    }
+
+    pub fn grid(&self) -> Grid {
+        let grid = if self.big {
+            Grid::big()
+        } else {
+            Grid::normal()
+        };
+        Grid {
+            mirrored: self.mirrored,
+            upside_down: self.upside_down,
+            ..grid
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Replay {
+    pub seed: u64,
+    pub grid: GridSettings,
+    pub visibility: StackVisibility,
+    // lines cleared and frames played by the end of the recording, and
+    // whether the game was over or quit then
+    pub score: u32,
+    pub frames: u64,
+    pub gameover: bool,
+    pub events: Vec<(u64, Event)>,
+}
+impl Replay {
+    pub fn load<P: AsRef<Path>>(path: P) -> GameResult<Replay> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        serde_json::from_str(&text)
+            .map_err(|e| GameError::ResourceLoadError(format!("{}: {}", path.display(), e)))
+    }
+}
+
+/// A game played from a replay, up to some frame.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cursor {
+    pub frame: u64,
+    // the first event not played yet
+    event: usize,
+    pub state: GameState,
+}
+impl Cursor {
+    /// The game of `replay` as it started.
+    pub fn new(replay: &Replay) -> Self {
+        let mut state = GameState::with_seed(replay.seed);
+        state.grid = replay.grid.grid();
+        state.stack_changed();
+        state.visibility = replay.visibility;
+        Cursor {
+            frame: 0,
+            event: 0,
+            state,
+        }
+    }
+
+    /// Plays the events of the frame and then the frame.
+    pub fn step(&mut self, replay: &Replay) {
+        self.play_events(replay);
+        self.state.tick();
+        self.frame += 1;
+    }
+
+    /// Plays the events of the frame the replay ends on, the game was quit
+    /// before playing it.
+    pub fn finish(&mut self, replay: &Replay) {
+        self.play_events(replay);
+    }
+
+    fn play_events(&mut self, replay: &Replay) {
+        while let Some((frame, event)) = replay.events.get(self.event) {
+            if *frame > self.frame {
+                break;
+            }
+            match event {
+                Event::Input(input) => self.state.handle_input(*input),
+                Event::Pause => self.state.pause = !self.state.pause,
+                Event::Visibility => self.state.visibility = self.state.visibility.next(),
+            }
+            self.event += 1;
+        }
+    }
+}
+
+/// Records the game being played, until its replay is saved.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Recorder {
+    replay: Replay,
+    saved: bool,
+}
+impl Recorder {
+    pub fn new(seed: u64, grid: Grid, visibility: StackVisibility) -> Self {
+        Recorder {
+            replay: Replay {
+                seed,
+                grid: GridSettings::of(grid),
+                visibility,
+                score: 0,
+                frames: 0,
+                gameover: false,
+                events: vec![],
+            },
+            saved: false,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.replay.seed
+    }
+
+    /// Records `event` on the frame being played.
+    pub fn record(&mut self, event: Event) {
+        if !self.saved {
+            self.replay.events.push((self.replay.frames, event));
+        }
+    }
+
+    /// Counts a frame played.
+    pub fn tick(&mut self) {
+        if !self.saved {
+            self.replay.frames += 1;
+        }
+    }
+
+    /// Stops the recording the first time this is called and gives back the
+    /// replay, unless nothing was played.
+    fn end(&mut self, score: u32, gameover: bool) -> Option<&Replay> {
+        if self.saved || self.replay.events.is_empty() {
+            self.saved = true;
+            return None;
+        }
+        self.saved = true;
+        self.replay.score = score;
+        self.replay.gameover = gameover;
+        Some(&self.replay)
+    }
+
+    /// Writes the replay the first time this is called, unless nothing was
+    /// played, and gives back where it went.
+    pub fn save(&mut self, score: u32, gameover: bool) -> GameResult<Option<PathBuf>> {
+        let Some(replay) = self.end(score, gameover) else {
+            return Ok(None);
+        };
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let path = PathBuf::from(REPLAYS_DIR).join(format!("{}-{:016x}.json", time, replay.seed));
+        let json =
+            serde_json::to_string(replay).map_err(|e| GameError::CustomError(e.to_string()))?;
+        fs::create_dir_all(REPLAYS_DIR)?;
+        fs::write(&path, json)?;
+        Ok(Some(path))
+    }
+}
+
+/// Plays `replay` to its end, failing when the game doesn't end the way the
+/// replay claims.
+fn check(replay: &Replay) -> Result<Cursor, String> {
+    if !replay.events.windows(2).all(|pair| pair[0].0 <= pair[1].0) {
+        return Err("the events are out of order".to_string());
+    }
+    if let Some((frame, _)) = replay
+        .events
+        .last()
+        .filter(|(frame, _)| *frame > replay.frames)
+    {
+        return Err(format!("an event comes on frame {} after the end", frame));
+    }
+    let mut cursor = Cursor::new(replay);
+    while cursor.frame < replay.frames {
+        if cursor.state.gameover {
+            return Err(format!(
+                "the game is over on frame {} but the replay goes on to frame {}",
+                cursor.frame, replay.frames
+            ));
+        }
+        cursor.step(replay);
+    }
+    cursor.finish(replay);
+    let state = &cursor.state;
+    let ended = |gameover| if gameover { "is over" } else { "is quit" };
+    if state.gameover != replay.gameover {
+        return Err(format!(
+            "the replay claims the game {} but it {}",
+            ended(replay.gameover),
+            ended(state.gameover)
+        ));
+    }
+    if state.score != replay.score {
+        return Err(format!(
+            "the replay claims a score of {} but the game ends with {}",
+            replay.score, state.score
+        ));
+    }
+    Ok(cursor)
+}
+
+/// Plays the replay at `path` to its end and checks it ends the way it
+/// claims, with the score it claims.
+pub fn verify<P: AsRef<Path>>(path: P) -> GameResult {
+    let path = path.as_ref();
+    let replay = Replay::load(path)?;
+    let cursor = check(&replay).map_err(|reason| {
+        GameError::ResourceLoadError(format!("{}: {}", path.display(), reason))
+    })?;
+    let state = &cursor.state;
+    // the score of a game is the lines it cleared
+    println!(
+        "{} lines, {} pieces, {}, {}",
+        state.score,
+        state.seats[0].pieces,
+        clock(replay.frames),
+        if state.gameover { "game over" } else { "quit" }
+    );
+    Ok(())
+}
+
+/// Plays a replay back in the window.
+pub struct Playback {
+    replay: Replay,
+    cursor: Cursor,
+    // the game every `SNAPSHOT_FRAMES` frames, as far as it was played
+    snapshots: Vec<Cursor>,
+    paused: bool,
+    speed: usize,
+    // frames owed to the replay by the speed, played once there's a whole one
+    progress: f32,
+}
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        let cursor = Cursor::new(&replay);
+        Playback {
+            snapshots: vec![cursor.clone()],
+            replay,
+            cursor,
+            paused: false,
+            speed: NORMAL_SPEED,
+            progress: 0.0,
+        }
+    }
+
+    fn advance(&mut self) {
+        if self.cursor.frame >= self.replay.frames {
+            return;
+        }
+        self.cursor.step(&self.replay);
+        let frame = self.cursor.frame;
+        if frame == self.replay.frames {
+            self.cursor.finish(&self.replay);
+        }
+        if frame % SNAPSHOT_FRAMES == 0 && frame / SNAPSHOT_FRAMES == self.snapshots.len() as u64 {
+            self.snapshots.push(self.cursor.clone());
+        }
+    }
+
+    /// Goes to `frame`, from the last snapshot before it.
+    fn seek(&mut self, frame: u64) {
+        let frame = frame.min(self.replay.frames);
+        let snapshot = (frame / SNAPSHOT_FRAMES).min(self.snapshots.len() as u64 - 1);
+        if frame < self.cursor.frame || snapshot * SNAPSHOT_FRAMES > self.cursor.frame {
+            self.cursor = self.snapshots[snapshot as usize].clone();
+        }
+        while self.cursor.frame < frame {
+            self.advance();
+        }
+        self.progress = 0.0;
+    }
+}
+
+/// `frames` as minutes, seconds and tenths.
+fn clock(frames: u64) -> String {
+    let tenths = frames * 10 / FPS as u64;
+    format!("{}:{:02}.{}", tenths / 600, tenths / 10 % 60, tenths % 10)
+}
+
+impl event::EventHandler<GameError> for Playback {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        while timer::check_update_time(ctx, FPS) {
+            if !self.paused {
+                self.progress += SPEEDS[self.speed];
+                while self.progress >= 1.0 {
+                    self.advance();
+                    self.progress -= 1.0;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
+        self.cursor.state.draw_board(ctx, &Layout::single())?;
+        let mut text = Text::new(format!(
+            "{} / {}  x{}{}\nspace pause  , . frame  left right seek  up down speed",
+            clock(self.cursor.frame),
+            clock(self.replay.frames),
+            SPEEDS[self.speed],
+            if self.paused { "  paused" } else { "" }
+        ));
+        text.set_font(Font::default(), PxScale::from(32.0));
+        graphics::draw(
+            ctx,
+            &text,
+            DrawParam::default().dest(Point2 {
+                x: 10.0,
+                y: SCREEN_SIZE.1 - 80.0,
+            }),
+        )?;
+        graphics::present(ctx)?;
+        timer::yield_now();
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        _repeat: bool,
+    ) {
+        let frame = self.cursor.frame;
+        match keycode {
+            KeyCode::Space => self.paused = !self.paused,
+            KeyCode::Period => {
+                self.paused = true;
+                self.advance();
+            }
+            KeyCode::Comma => {
+                self.paused = true;
+                self.seek(frame.saturating_sub(1));
+            }
+            KeyCode::Left => self.seek(frame.saturating_sub(SEEK_FRAMES)),
+            KeyCode::Right => self.seek(frame + SEEK_FRAMES),
+            KeyCode::Up => self.speed = (self.speed + 1).min(SPEEDS.len() - 1),
+            KeyCode::Down => self.speed = self.speed.saturating_sub(1),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::Bot;
+
+    #[test]
+    fn events_round_trip_through_their_letters() {
+        let events = [
+            Event::Input(Input::Left),
+            Event::Input(Input::Right),
+            Event::Input(Input::SoftDrop),
+            Event::Input(Input::Rotate),
+            Event::Input(Input::Hold),
+            Event::Pause,
+            Event::Visibility,
+        ];
+        let letters: String = events.iter().map(|event| char::from(*event)).collect();
+        assert_eq!(letters, "LRDUHPI");
+        for event in events {
+            assert_eq!(Event::try_from(char::from(event)), Ok(event));
+        }
+        assert_eq!(Event::try_from('x'), Err("`x` is not an event".to_string()));
+        let json = serde_json::to_string(&[(0, Event::Input(Input::Left)), (37, Event::Pause)]);
+        assert_eq!(json.unwrap(), r#"[[0,"L"],[37,"P"]]"#);
+        let parsed: Vec<(u64, Event)> = serde_json::from_str(r#"[[5,"I"],[6,"H"]]"#).unwrap();
+        assert_eq!(
+            parsed,
+            [(5, Event::Visibility), (6, Event::Input(Input::Hold))]
+        );
+        assert!(serde_json::from_str::<Vec<(u64, Event)>>(r#"[[5,"Q"]]"#).is_err());
+    }
+
+    // plays `frames` frames of the game of `seed` with a bot, recording them,
+    // then presses `last` and stops the recording
+    fn record(seed: u64, frames: u64, last: Input) -> (GameState, Replay) {
+        let mut state = GameState::with_seed(seed);
+        let mut recorder = Recorder::new(seed, state.grid, state.visibility);
+        let mut bot = Bot::new(2);
+        for _ in 0..frames {
+            if let Some(input) = bot.input(&state) {
+                recorder.record(Event::Input(input));
+                state.handle_input(input);
+            }
+            let over = state.gameover;
+            state.tick();
+            if !over {
+                recorder.tick();
+            }
+        }
+        recorder.record(Event::Input(last));
+        state.handle_input(last);
+        let replay = recorder.end(state.score, state.gameover).unwrap().clone();
+        assert_eq!(recorder.end(state.score, state.gameover), None);
+        (state, replay)
+    }
+
+    #[test]
+    fn replays_play_the_game_again() {
+        let (state, replay) = record(3, 2000, Input::Rotate);
+        assert!(state.score > 0 && !state.gameover);
+        assert_eq!(
+            replay.events.last(),
+            Some(&(2000, Event::Input(Input::Rotate)))
+        );
+        let cursor = check(&replay).unwrap();
+        assert_eq!(cursor.frame, 2000);
+        assert_eq!(cursor.state.stack_cells(), state.stack_cells());
+        assert_eq!(cursor.state.score, replay.score);
+        let mut playback = Playback::new(replay.clone());
+        playback.seek(replay.frames);
+        assert_eq!(playback.cursor.state.stack_cells(), state.stack_cells());
+
+        let claims = |change: fn(&mut Replay)| {
+            let mut replay = replay.clone();
+            change(&mut replay);
+            check(&replay).err()
+        };
+        assert_eq!(
+            claims(|replay| replay.score += 1),
+            Some(format!(
+                "the replay claims a score of {} but the game ends with {}",
+                state.score + 1,
+                state.score
+            ))
+        );
+        assert_eq!(
+            claims(|replay| replay.gameover = true),
+            Some("the replay claims the game is over but it is quit".to_string())
+        );
+        assert_eq!(
+            claims(|replay| replay.events.push((2001, Event::Pause))),
+            Some("an event comes on frame 2001 after the end".to_string())
+        );
+    }
+
+    #[test]
+    fn replays_end_when_the_game_is_over() {
+        // dropping every figure in the middle tops out
+        let mut state = GameState::with_seed(8);
+        let mut recorder = Recorder::new(8, state.grid, state.visibility);
+        while !state.gameover {
+            recorder.record(Event::Input(Input::SoftDrop));
+            state.handle_input(Input::SoftDrop);
+            state.tick();
+            recorder.tick();
+        }
+        for _ in 0..3 {
+            state.tick();
+        }
+        let replay = recorder.end(state.score, true).unwrap().clone();
+        assert!(check(&replay).unwrap().state.gameover);
+        let mut longer = replay.clone();
+        longer.frames += 1;
+        assert_eq!(
+            check(&longer).err(),
+            Some(format!(
+                "the game is over on frame {} but the replay goes on to frame {}",
+                replay.frames,
+                replay.frames + 1
+            ))
+        );
+    }
+}
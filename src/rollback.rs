@@ -0,0 +1,435 @@
+//! Rollback netcode: the game never waits for the other player. Until their
+//! inputs for a frame arrive they're guessed to be nothing, and when the real
+//! ones turn out to be different the boards go back to the snapshot taken on
+//! that frame and play again up to the present.
+//!
+//! Every frame each player sends the line
+//!
+//! ```text
+//! input <n> <inputs> [restart]
+//! ```
+//!
+//! with the same `<inputs>` and `restart` as the lockstep protocol in
+//! `src/net.rs`. There's no garbage in it, the garbage a board sends can
+//! change when the frames before get played again, and the `result` of a match
+//! is only sent once the frame it ended on can't change anymore.
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+use ggez::event::{self, Button, GamepadId, KeyCode, KeyMods};
+use ggez::graphics::{self, DrawParam, Font, PxScale, Text};
+use ggez::{timer, Context, GameResult};
+use mint::Point2;
+
+use crate::input::{Input, KeyBindings};
+use crate::net::{
+    decode_inputs, draw_match, encode_inputs, is_result, play, protocol_error, result_line, winner,
+    Frame, Link, PlayerKeys, Transport,
+};
+use crate::settings::{Configurable, Settings};
+use crate::spectate::Watched;
+use crate::{random_seed, GameState, FPS};
+
+// Frames the game can guess ahead of the last inputs received from the other
+// player before waiting for them, so a rollback never replays more than this.
+const MAX_PREDICTION: u64 = 20;
+
+/// A match kept in sync with rollbacks over `transport`, board 0 is the one of
+/// this player.
+pub struct Rollback<T: Transport> {
+    transport: T,
+    // the first frame the other player's inputs for may still be unknown
+    base: u64,
+    // both boards at the start of every frame from `base` on, the last one is
+    // the present
+    snapshots: VecDeque<[GameState; 2]>,
+    // what each player did on every frame from `base` on, the other player's
+    // only as far as they arrived
+    local: VecDeque<Frame>,
+    remote: VecDeque<Frame>,
+    // times the boards went back to play frames again
+    pub rollbacks: u64,
+}
+impl<T: Transport> Rollback<T> {
+    pub fn new(transport: T, seed: u64) -> Self {
+        let mut snapshots = VecDeque::new();
+        snapshots.push_back([GameState::with_seed(seed), GameState::with_seed(seed)]);
+        Rollback {
+            transport,
+            base: 0,
+            snapshots,
+            local: VecDeque::new(),
+            remote: VecDeque::new(),
+            rollbacks: 0,
+        }
+    }
+
+    /// The boards as of the present, with the guesses made so far.
+    pub fn boards(&self) -> &[GameState; 2] {
+        self.snapshots.back().unwrap()
+    }
+
+    // the frame about to be played
+    fn frame(&self) -> u64 {
+        self.base + self.snapshots.len() as u64 - 1
+    }
+
+    /// The other player's inputs are too far behind to keep guessing them.
+    pub fn waiting(&self) -> bool {
+        self.frame() >= self.base + self.remote.len() as u64 + MAX_PREDICTION
+    }
+
+    /// Takes the inputs the other player sent, going back to play again the
+    /// frames where they don't match the guess.
+    pub fn poll(&mut self) -> GameResult {
+        let mut wrong_guess = None;
+        while let Some(line) = self.transport.try_receive()? {
+            if is_result(&line) {
+                continue;
+            }
+            let n = self.base + self.remote.len() as u64;
+            let frame = decode(&line, n).map_err(protocol_error)?;
+            if n < self.frame() && frame != Frame::default() {
+                wrong_guess = wrong_guess.or(Some(n));
+            }
+            self.remote.push_back(frame);
+        }
+        if let Some(n) = wrong_guess {
+            self.rollbacks += 1;
+            let present = self.frame();
+            self.snapshots.truncate((n - self.base) as usize + 1);
+            for n in n..present {
+                let boards = self.simulate(n);
+                self.snapshots.push_back(boards);
+            }
+        }
+        // frames played with the real inputs of both players won't change
+        while !self.remote.is_empty() && self.snapshots.len() > 1 {
+            let over = winner(&self.snapshots[0]).is_some();
+            self.snapshots.pop_front();
+            self.local.pop_front();
+            self.remote.pop_front();
+            if let (false, Some(line)) = (over, result_line(&self.snapshots[0], self.base)) {
+                self.transport.send(&line)?;
+            }
+            self.base += 1;
+        }
+        Ok(())
+    }
+
+    /// Plays the present frame with what this player did, sending it to the
+    /// other one.
+    pub fn advance(&mut self, local: Frame) -> GameResult {
+        let n = self.frame();
+        self.transport.send(&encode(&local, n))?;
+        self.local.push_back(local);
+        let boards = self.simulate(n);
+        self.snapshots.push_back(boards);
+        Ok(())
+    }
+
+    // both boards at the end of frame `n`, from the snapshot at its start
+    fn simulate(&self, n: u64) -> [GameState; 2] {
+        let i = (n - self.base) as usize;
+        let mut boards = self.snapshots[i].clone();
+        let guess = Frame::default();
+        let frames = [&self.local[i], self.remote.get(i).unwrap_or(&guess)];
+        let over = winner(&boards).is_some();
+        if !over {
+            for (board, frame) in boards.iter_mut().zip(frames) {
+                play(board, frame);
+            }
+        }
+        let sent = [
+            std::mem::take(&mut boards[0].garbage.outgoing),
+            std::mem::take(&mut boards[1].garbage.outgoing),
+        ];
+        for (board, lines) in boards.iter_mut().zip(sent.iter().rev()) {
+            let ticks = board.ticks;
+            board.garbage.receive(*lines, ticks);
+        }
+        if over && frames.iter().any(|frame| frame.restart) {
+            for board in boards.iter_mut() {
+                board.restart();
+            }
+        }
+        boards
+    }
+}
+
+fn encode(frame: &Frame, n: u64) -> String {
+    let restart = if frame.restart { " restart" } else { "" };
+    format!("input {} {}{}", n, encode_inputs(&frame.inputs), restart)
+}
+
+fn decode(line: &str, n: u64) -> Result<Frame, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (frame, inputs, restart) = match words[..] {
+        ["input", frame, inputs] => (frame, inputs, false),
+        ["input", frame, inputs, "restart"] => (frame, inputs, true),
+        _ => return Err(format!("expected input {}, got `{}`", n, line)),
+    };
+    if frame.parse::<u64>() != Ok(n) {
+        return Err(format!("expected input {}, got `{}`", n, line));
+    }
+    Ok(Frame {
+        inputs: decode_inputs(inputs)?,
+        garbage: 0,
+        restart,
+    })
+}
+
+/// Two ends of a connection inside the same program, every line taking
+/// `latency` to get to the other end. It stands in for the network to try the
+/// rollbacks without a second machine.
+pub struct Loopback {
+    sender: Sender<(Instant, String)>,
+    receiver: Receiver<(Instant, String)>,
+    latency: Duration,
+    // lines sent to this end, with when they get here
+    in_flight: VecDeque<(Instant, String)>,
+}
+impl Loopback {
+    pub fn pair(latency: Duration) -> (Loopback, Loopback) {
+        let (to_a, from_b) = mpsc::channel();
+        let (to_b, from_a) = mpsc::channel();
+        let end = |sender, receiver| Loopback {
+            sender,
+            receiver,
+            latency,
+            in_flight: VecDeque::new(),
+        };
+        (end(to_b, from_b), end(to_a, from_a))
+    }
+}
+impl Transport for Loopback {
+    fn send(&mut self, line: &str) -> GameResult {
+        self.sender
+            .send((Instant::now() + self.latency, line.to_string()))
+            .map_err(|_| protocol_error("the other player left".to_string()))
+    }
+
+    fn try_receive(&mut self) -> GameResult<Option<String>> {
+        self.in_flight.extend(self.receiver.try_iter());
+        match self.in_flight.front() {
+            Some((arrival, _)) if *arrival <= Instant::now() => {
+                Ok(self.in_flight.pop_front().map(|(_, line)| line))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// A network match with rollbacks on screen, this player's board on the left.
+pub struct RollbackVersus {
+    game: Rollback<Link>,
+    // what this player did since the last frame
+    pending: Frame,
+    keys: PlayerKeys,
+}
+impl RollbackVersus {
+    pub fn new(link: Link, seed: u64) -> Self {
+        RollbackVersus {
+            game: Rollback::new(link, seed),
+            pending: Frame::default(),
+            keys: PlayerKeys::default(),
+        }
+    }
+}
+
+impl Configurable for RollbackVersus {
+    fn configure(&mut self, settings: &Settings) {
+        self.keys.configure(settings);
+    }
+}
+
+impl Watched for RollbackVersus {
+    fn boards(&self) -> Vec<&GameState> {
+        self.game.boards().iter().collect()
+    }
+}
+
+impl event::EventHandler<ggez::GameError> for RollbackVersus {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        while timer::check_update_time(ctx, FPS) {
+            self.game.poll()?;
+            if !self.game.waiting() {
+                self.keys.repeat(&mut self.pending, &self.game.boards()[0]);
+                self.game.advance(std::mem::take(&mut self.pending))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
+        draw_match(ctx, self.game.boards())?;
+        graphics::present(ctx)?;
+        timer::yield_now();
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        repeat: bool,
+    ) {
+        if keycode == KeyCode::R {
+            self.pending.restart = winner(self.game.boards()).is_some();
+        } else {
+            self.keys.press(keycode, repeat, &mut self.pending);
+        }
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
+        self.keys.release(keycode);
+    }
+
+    fn gamepad_button_down_event(&mut self, _ctx: &mut Context, btn: Button, _id: GamepadId) {
+        if let Some(input) = Input::from_button(btn) {
+            self.pending.inputs.push(input);
+        }
+    }
+}
+
+/// Two players on one machine, each with their own game connected to the
+/// other through a `Loopback`. The screen shows the left player's game, so
+/// the right player's moves show up late and the rollbacks can be seen.
+pub struct LoopbackVersus {
+    games: [Rollback<Loopback>; 2],
+    keys: [KeyBindings; 2],
+    pending: [Frame; 2],
+}
+impl LoopbackVersus {
+    pub fn new(latency: Duration) -> Self {
+        let (a, b) = Loopback::pair(latency);
+        let seed = random_seed();
+        LoopbackVersus {
+            games: [Rollback::new(a, seed), Rollback::new(b, seed)],
+            keys: [KeyBindings::wasd(), KeyBindings::arrows_right_hand()],
+            pending: [Frame::default(), Frame::default()],
+        }
+    }
+}
+
+impl Configurable for LoopbackVersus {}
+
+impl Watched for LoopbackVersus {
+    fn boards(&self) -> Vec<&GameState> {
+        self.games[0].boards().iter().collect()
+    }
+}
+
+impl event::EventHandler<ggez::GameError> for LoopbackVersus {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        while timer::check_update_time(ctx, FPS) {
+            for (game, pending) in self.games.iter_mut().zip(self.pending.iter_mut()) {
+                game.poll()?;
+                if !game.waiting() {
+                    game.advance(std::mem::take(pending))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
+        draw_match(ctx, self.games[0].boards())?;
+        let mut text = Text::new(format!("Rollbacks : {}", self.games[0].rollbacks));
+        text.set_font(Font::default(), PxScale::from(32.0));
+        graphics::draw(
+            ctx,
+            &text,
+            DrawParam::default().dest(Point2 { x: 10.0, y: 10.0 }),
+        )?;
+        graphics::present(ctx)?;
+        timer::yield_now();
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        _repeat: bool,
+    ) {
+        for ((game, keys), pending) in self
+            .games
+            .iter()
+            .zip(self.keys.iter())
+            .zip(self.pending.iter_mut())
+        {
+            if keycode == KeyCode::R {
+                pending.restart = winner(game.boards()).is_some();
+            } else if let Some(input) = keys.input(keycode) {
+                pending.inputs.push(input);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    // what player `n` presses on `frame` of the test
+    fn script(n: u64, frame: u64) -> Frame {
+        let inputs = match (frame * 5 + n * 2) % 9 {
+            0 => vec![Input::Left],
+            1 => vec![Input::Rotate],
+            2 => vec![Input::Right, Input::Right],
+            3 | 4 => vec![Input::SoftDrop],
+            _ => vec![],
+        };
+        Frame {
+            inputs,
+            ..Frame::default()
+        }
+    }
+
+    #[test]
+    fn rollbacks_end_on_the_boards_played_directly() {
+        const FRAMES: u64 = 240;
+        let seed = 7;
+        let (a, b) = Loopback::pair(Duration::from_millis(15));
+        let mut games = [Rollback::new(a, seed), Rollback::new(b, seed)];
+        let mut played = [0; 2];
+        while games.iter().any(|game| game.base < FRAMES) {
+            for (n, game) in games.iter_mut().enumerate() {
+                game.poll().unwrap();
+                if played[n] < FRAMES && !game.waiting() {
+                    game.advance(script(n as u64, played[n])).unwrap();
+                    played[n] += 1;
+                }
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(games.iter().all(|game| game.rollbacks > 0));
+        let [a, b] = games.map(|game| game.boards().clone());
+        assert_eq!(a[0], b[1]);
+        assert_eq!(a[1], b[0]);
+        // the same match with both boards played in one place
+        let mut boards = [GameState::with_seed(seed), GameState::with_seed(seed)];
+        for frame in 0..FRAMES {
+            for (n, board) in boards.iter_mut().enumerate() {
+                play(board, &script(n as u64, frame));
+            }
+            let sent = boards
+                .each_mut()
+                .map(|board| std::mem::take(&mut board.garbage.outgoing));
+            for (board, lines) in boards.iter_mut().zip(sent.iter().rev()) {
+                let ticks = board.ticks;
+                board.garbage.receive(*lines, ticks);
+            }
+        }
+        assert_eq!(a, boards);
+        assert!(boards[0].seats[0].pieces > 1);
+    }
+}
@@ -0,0 +1,501 @@
+//! Battle royale: the player against 10 to 99 bots, each one on their own
+//! board. Every attack goes to one opponent picked by the attacker's targeting
+//! and the last one to attack a board that tops out gets the KO, and with it a
+//! badge plus the badges of the one knocked out. Badges make attacks stronger.
+use ggez::event::{self, Button, GamepadId, KeyCode, KeyMods};
+use ggez::graphics::{self, Color, DrawMode, DrawParam, Font, MeshBuilder, PxScale, Rect, Text};
+use ggez::{timer, Context, GameResult};
+use mint::Point2;
+use oorandom::Rand32;
+
+use crate::bot::Bot;
+use crate::input::{Held, Input, KeyBindings};
+use crate::settings::{Configurable, Handling, Settings};
+use crate::spectate::Watched;
+use crate::versus::draw_banner;
+use crate::{random_seed, GameState, Layout, FPS, SCREEN_SIZE};
+
+pub const MIN_BOTS: usize = 10;
+pub const MAX_BOTS: usize = 99;
+// Badges needed for each extra quarter of attack.
+const BADGE_LEVELS: [u32; 4] = [2, 6, 14, 30];
+// The mini-boards of the opponents: size of a cell, boards in a row of each
+// side of the screen and room taken by each board.
+const MINI_CELL: f32 = 4.0;
+const MINI_COLUMNS: usize = 10;
+const MINI_SPACING: (f32, f32) = (48.0, 100.0);
+
+/// Who an attack goes to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Targeting {
+    Random,
+    // the ones attacking us
+    Attackers,
+    // the one closest to topping out
+    KOs,
+    // the one with the most badges
+    Badges,
+}
+impl Targeting {
+    const ALL: [Targeting; 4] = [
+        Targeting::Random,
+        Targeting::Attackers,
+        Targeting::KOs,
+        Targeting::Badges,
+    ];
+
+    fn name(&self) -> &'static str {
+        match *self {
+            Targeting::Random => "Random",
+            Targeting::Attackers => "Attackers",
+            Targeting::KOs => "KOs",
+            Targeting::Badges => "Badges",
+        }
+    }
+}
+
+struct Entrant {
+    board: GameState,
+    // `None` for the player
+    bot: Option<Bot>,
+    targeting: Targeting,
+    // the last one this entrant attacked, and the last one that attacked it
+    target: Option<usize>,
+    attacker: Option<usize>,
+    badges: u32,
+    kos: u32,
+    // 1 for the winner, set once out of the game
+    place: Option<usize>,
+}
+
+pub struct Royale {
+    // the player is the first one
+    entrants: Vec<Entrant>,
+    rng: Rand32,
+    pause: bool,
+    // the player's keys and how they repeat when held, from the settings
+    keys: KeyBindings,
+    handling: Handling,
+    held: Held,
+}
+impl Royale {
+    pub fn new(bots: usize) -> Self {
+        let mut rng = Rand32::new(random_seed());
+        let mut entrants = vec![];
+        for n in 0..=bots {
+            let seed = (rng.rand_u32() as u64) << 32 | rng.rand_u32() as u64;
+            entrants.push(Entrant {
+                board: GameState::with_seed(seed),
+                bot: (n > 0).then(|| Bot::new(rng.rand_range(4..17))),
+                targeting: Targeting::ALL[rng.rand_range(0..4) as usize],
+                target: None,
+                attacker: None,
+                badges: 0,
+                kos: 0,
+                place: None,
+            });
+        }
+        entrants[0].targeting = Targeting::Random;
+        Royale {
+            entrants,
+            rng,
+            pause: false,
+            keys: KeyBindings::arrows(),
+            handling: Handling::default(),
+            held: Held::default(),
+        }
+    }
+
+    fn alive(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.entrants.len()).filter(|n| self.entrants[*n].place.is_none())
+    }
+
+    /// The match is over for the player once they are out or the last one left.
+    fn over(&self) -> bool {
+        self.entrants[0].place.is_some()
+    }
+
+    /// Who entrant `n` attacks now.
+    fn pick_target(&mut self, n: usize) -> Option<usize> {
+        let others: Vec<usize> = self.alive().filter(|other| *other != n).collect();
+        let attackers: Vec<usize> = others
+            .iter()
+            .copied()
+            .filter(|other| self.entrants[*other].target == Some(n))
+            .collect();
+        let candidates = match self.entrants[n].targeting {
+            Targeting::Attackers if !attackers.is_empty() => attackers,
+            Targeting::KOs => others
+                .iter()
+                .copied()
+                .max_by_key(|other| self.entrants[*other].board.stack_height())
+                .into_iter()
+                .collect(),
+            Targeting::Badges => others
+                .iter()
+                .copied()
+                .max_by_key(|other| self.entrants[*other].badges)
+                .into_iter()
+                .collect(),
+            _ => others,
+        };
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(candidates[self.rng.rand_range(0..candidates.len() as u32) as usize])
+    }
+
+    /// Runs one frame of every board still in the game.
+    fn tick(&mut self) {
+        if self.over() || self.pause {
+            return;
+        }
+        let player = &mut self.entrants[0].board;
+        for input in self.held.repeats(&self.handling, player.grid.width) {
+            player.handle_input(input);
+        }
+        for entrant in self.entrants.iter_mut().filter(|e| e.place.is_none()) {
+            if let Some(input) = entrant
+                .bot
+                .as_mut()
+                .and_then(|bot| bot.input(&entrant.board))
+            {
+                entrant.board.handle_input(input);
+            }
+            entrant.board.tick();
+        }
+        for n in self.alive().collect::<Vec<_>>() {
+            let lines = std::mem::take(&mut self.entrants[n].board.garbage.outgoing);
+            if lines == 0 {
+                continue;
+            }
+            let lines = lines * (4 + badge_level(self.entrants[n].badges)) / 4;
+            if let Some(target) = self.pick_target(n) {
+                let board = &mut self.entrants[target].board;
+                board.garbage.receive(lines, board.ticks);
+                self.entrants[target].attacker = Some(n);
+                self.entrants[n].target = Some(target);
+            }
+        }
+        // everyone topping out on the same frame shares the place
+        let left = self.alive().count();
+        let knocked_out: Vec<usize> = self
+            .alive()
+            .filter(|n| self.entrants[*n].board.gameover)
+            .collect();
+        for n in knocked_out.iter().copied() {
+            self.entrants[n].place = Some(left);
+            // an attacker topping out on the same frame gets no KO, whichever
+            // of the two comes first here
+            if let Some(attacker) = self.entrants[n].attacker {
+                if !self.entrants[attacker].board.gameover {
+                    self.entrants[attacker].kos += 1;
+                    self.entrants[attacker].badges += 1 + self.entrants[n].badges;
+                }
+            }
+        }
+        if let [winner] = self.alive().collect::<Vec<_>>()[..] {
+            self.entrants[winner].place = Some(1);
+        }
+    }
+
+    /// Draws the opponents' boards on both sides of the player's one.
+    fn draw_opponents(&self, ctx: &mut Context) -> GameResult {
+        let mut mesh = MeshBuilder::new();
+        let width = self.entrants[0].board.grid.width as f32 * MINI_CELL;
+        let height = self.entrants[0].board.grid.height as f32 * MINI_CELL;
+        let rows = (MAX_BOTS / 2 + 1) / MINI_COLUMNS;
+        let top = (SCREEN_SIZE.1 - rows as f32 * MINI_SPACING.1) / 2.0;
+        let side_width = MINI_COLUMNS as f32 * MINI_SPACING.0;
+        for (n, entrant) in self.entrants.iter().enumerate().skip(1) {
+            let i = n - 1;
+            let (side, i) = (i / (MAX_BOTS / 2 + 1), i % (MAX_BOTS / 2 + 1));
+            let left = if side == 0 {
+                10.0
+            } else {
+                SCREEN_SIZE.0 - 10.0 - side_width
+            };
+            let x = left + (i % MINI_COLUMNS) as f32 * MINI_SPACING.0;
+            let y = top + (i / MINI_COLUMNS) as f32 * MINI_SPACING.1;
+            // the player's target is framed in red, the ones attacking the
+            // player in orange
+            let frame = if self.entrants[0].target == Some(n) {
+                Some(Color::RED)
+            } else if entrant.target == Some(0) && entrant.place.is_none() {
+                Some(Color::new(1.0, 0.6, 0.0, 1.0))
+            } else {
+                None
+            };
+            if let Some(color) = frame {
+                mesh.rectangle(
+                    DrawMode::fill(),
+                    Rect::new(x - 2.0, y - 2.0, width + 4.0, height + 4.0),
+                    color,
+                )?;
+            }
+            let background = if entrant.place.is_some() {
+                Color::new(0.3, 0.0, 0.0, 1.0)
+            } else {
+                Color::new(0.15, 0.15, 0.15, 1.0)
+            };
+            mesh.rectangle(DrawMode::fill(), Rect::new(x, y, width, height), background)?;
+            if entrant.place.is_some() {
+                continue;
+            }
+            let board = &entrant.board;
+            let figures = board
+                .seats
+                .iter()
+                .filter_map(|seat| seat.actual_figure.as_ref());
+            let blocks = board
+                .static_blocks
+                .iter()
+                .chain(figures.flat_map(|fig| fig.blocks.iter()));
+            for block in blocks.filter(|block| block.y >= 0) {
+                mesh.rectangle(
+                    DrawMode::fill(),
+                    Rect::new(
+                        x + block.x as f32 * MINI_CELL,
+                        y + block.y as f32 * MINI_CELL,
+                        MINI_CELL,
+                        MINI_CELL,
+                    ),
+                    block.color,
+                )?;
+            }
+        }
+        let mesh = mesh.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::default())
+    }
+}
+
+/// Extra quarters of attack for having `badges`.
+fn badge_level(badges: u32) -> u32 {
+    BADGE_LEVELS
+        .iter()
+        .filter(|level| badges >= **level)
+        .count() as u32
+}
+
+impl Configurable for Royale {
+    fn configure(&mut self, settings: &Settings) {
+        self.keys = settings.controls.bindings();
+        self.handling = settings.handling;
+        self.held = Held::default();
+    }
+}
+
+impl Watched for Royale {
+    fn boards(&self) -> Vec<&GameState> {
+        vec![&self.entrants[0].board]
+    }
+}
+
+impl event::EventHandler<ggez::GameError> for Royale {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        while timer::check_update_time(ctx, FPS) {
+            self.tick();
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
+        let player = &self.entrants[0];
+        player.board.draw_board(ctx, &Layout::royale())?;
+        self.draw_opponents(ctx)?;
+        let keys: Vec<String> = Targeting::ALL
+            .iter()
+            .enumerate()
+            .map(|(n, targeting)| format!("{} {}", n + 1, targeting.name()))
+            .collect();
+        let mut text = Text::new(format!(
+            "Targeting : {}\n{}\nBadges : {}  KOs : {}\nLeft : {} / {}",
+            player.targeting.name(),
+            keys.join("  "),
+            player.badges,
+            player.kos,
+            self.alive().count(),
+            self.entrants.len()
+        ));
+        text.set_font(Font::default(), PxScale::from(32.0));
+        graphics::draw(
+            ctx,
+            &text,
+            DrawParam::default().dest(Point2 { x: 10.0, y: 10.0 }),
+        )?;
+        match player.place {
+            Some(1) => draw_banner(ctx, "You win !  press R to play again")?,
+            Some(place) => draw_banner(
+                ctx,
+                &format!(
+                    "You placed #{} of {}  press R to play again",
+                    place,
+                    self.entrants.len()
+                ),
+            )?,
+            None => (),
+        }
+        graphics::present(ctx)?;
+        timer::yield_now();
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        repeat: bool,
+    ) {
+        let targeting = match keycode {
+            KeyCode::Key1 => Some(Targeting::Random),
+            KeyCode::Key2 => Some(Targeting::Attackers),
+            KeyCode::Key3 => Some(Targeting::KOs),
+            KeyCode::Key4 => Some(Targeting::Badges),
+            _ => None,
+        };
+        if let Some(targeting) = targeting {
+            self.entrants[0].targeting = targeting;
+        } else if keycode == KeyCode::R {
+            *self = Royale {
+                keys: self.keys,
+                handling: self.handling,
+                ..Royale::new(self.entrants.len() - 1)
+            };
+        } else if keycode == KeyCode::P {
+            self.pause = !self.pause;
+        } else if let (false, Some(input)) = (
+            self.over() || self.pause || repeat,
+            self.keys.input(keycode),
+        ) {
+            self.entrants[0].board.handle_input(input);
+            self.held.press(input);
+        }
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
+        if let Some(input) = self.keys.input(keycode) {
+            self.held.release(input);
+        }
+    }
+
+    fn gamepad_button_down_event(&mut self, _ctx: &mut Context, btn: Button, _id: GamepadId) {
+        if let (false, Some(input)) = (self.over() || self.pause, Input::from_button(btn)) {
+            self.entrants[0].board.handle_input(input);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Block;
+
+    // a match where nobody moves, only what the tests do happens
+    fn royale(bots: usize) -> Royale {
+        let mut royale = Royale::new(bots);
+        royale.rng = Rand32::new(0);
+        for entrant in royale.entrants.iter_mut() {
+            entrant.bot = None;
+            entrant.targeting = Targeting::Random;
+        }
+        royale
+    }
+
+    fn targets(royale: &mut Royale, n: usize) -> Vec<usize> {
+        let mut targets: Vec<usize> = (0..50).filter_map(|_| royale.pick_target(n)).collect();
+        targets.sort();
+        targets.dedup();
+        targets
+    }
+
+    #[test]
+    fn picks_targets_by_their_targeting() {
+        let mut royale = royale(4);
+        royale.entrants[4].place = Some(5);
+        assert_eq!(targets(&mut royale, 1), [0, 2, 3]);
+        // attackers, or anyone when nobody attacks
+        royale.entrants[1].targeting = Targeting::Attackers;
+        assert_eq!(targets(&mut royale, 1), [0, 2, 3]);
+        royale.entrants[3].target = Some(1);
+        assert_eq!(targets(&mut royale, 1), [3]);
+        // the highest stack
+        royale.entrants[1].targeting = Targeting::KOs;
+        for y in 15..20 {
+            royale.entrants[2]
+                .board
+                .static_blocks
+                .push(Block::new(0, y, Color::WHITE));
+        }
+        royale.entrants[0]
+            .board
+            .static_blocks
+            .push(Block::new(0, 19, Color::WHITE));
+        assert_eq!(targets(&mut royale, 1), [2]);
+        // the most badges
+        royale.entrants[1].targeting = Targeting::Badges;
+        royale.entrants[0].badges = 3;
+        royale.entrants[3].badges = 1;
+        assert_eq!(targets(&mut royale, 1), [0]);
+        // nobody left
+        let mut royale = self::royale(1);
+        royale.entrants[0].place = Some(2);
+        assert_eq!(royale.pick_target(1), None);
+    }
+
+    #[test]
+    fn badges_strengthen_attacks_by_levels() {
+        let levels: Vec<u32> = [0, 1, 2, 5, 6, 13, 14, 29, 30, 100]
+            .iter()
+            .map(|badges| badge_level(*badges))
+            .collect();
+        assert_eq!(levels, [0, 0, 1, 1, 2, 2, 3, 3, 4, 4]);
+    }
+
+    #[test]
+    fn kos_take_the_badges_of_the_one_knocked_out() {
+        let mut royale = royale(3);
+        royale.entrants[2].badges = 3;
+        royale.entrants[2].attacker = Some(1);
+        royale.entrants[2].board.gameover = true;
+        royale.tick();
+        assert_eq!(royale.entrants[2].place, Some(4));
+        assert_eq!(royale.entrants[1].kos, 1);
+        assert_eq!(royale.entrants[1].badges, 4);
+        assert_eq!(royale.alive().collect::<Vec<_>>(), [0, 1, 3]);
+    }
+
+    #[test]
+    fn topping_out_together_shares_the_place() {
+        // the attacker tops out too, before or after its victim in the list
+        for (attacker, victim) in [(1, 2), (2, 1)] {
+            let mut royale = royale(3);
+            royale.entrants[victim].attacker = Some(attacker);
+            royale.entrants[attacker].attacker = Some(3);
+            royale.entrants[victim].board.gameover = true;
+            royale.entrants[attacker].board.gameover = true;
+            royale.tick();
+            assert_eq!(royale.entrants[victim].place, Some(4));
+            assert_eq!(royale.entrants[attacker].place, Some(4));
+            assert_eq!(royale.entrants[attacker].kos, 0);
+            assert_eq!(royale.entrants[attacker].badges, 0);
+            assert_eq!(royale.entrants[3].kos, 1);
+            assert_eq!(royale.entrants[3].badges, 1);
+        }
+    }
+
+    #[test]
+    fn the_last_one_left_wins() {
+        let mut royale = royale(2);
+        royale.entrants[1].board.gameover = true;
+        royale.tick();
+        assert_eq!(royale.entrants[1].place, Some(3));
+        assert!(!royale.over());
+        royale.entrants[2].board.gameover = true;
+        royale.tick();
+        assert_eq!(royale.entrants[2].place, Some(2));
+        assert_eq!(royale.entrants[0].place, Some(1));
+        assert!(royale.over());
+    }
+}
@@ -0,0 +1,358 @@
+//! `tetris-server`, a server for network versus. Players join a lobby with
+//! `tetris --connect <server>:<port> <name> <lobby>`, the server pairs them two
+//! by two and relays their matches standing in for the host, so nobody needs
+//! to open a port. The result of every match goes to a file.
+//!
+//! ```text
+//! tetris-server <port> [--rollback] [--results <file>]
+//! ```
+//!
+//! The protocol is the one described in `src/net.rs`.
+use std::collections::{HashMap, VecDeque};
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tetris::PROTOCOL_VERSION;
+
+struct Player {
+    name: String,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+impl Player {
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.writer, "{}", line)
+    }
+
+    /// Checks the player answered the greeting of a match in our version.
+    fn confirm(&mut self) -> io::Result<()> {
+        let line = self.receive()?;
+        if line != format!("tetris {}", PROTOCOL_VERSION) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected greeting `{}` from {}", line, self.name),
+            ));
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} left", self.name),
+            ));
+        }
+        Ok(line.trim().to_string())
+    }
+}
+
+/// Leaves `player` waiting in `lobby`, or takes the one waiting there out
+/// of it to play them, the one who waited first.
+fn pair<T>(lobbies: &mut HashMap<String, T>, lobby: &str, player: T) -> Option<[T; 2]> {
+    match lobbies.remove(lobby) {
+        Some(waiting) => Some([waiting, player]),
+        None => {
+            lobbies.insert(lobby.to_string(), player);
+            None
+        }
+    }
+}
+
+/// Who won a match, as the two games of it reported.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Verdict {
+    Won(usize),
+    // both games say they won, or both that they lost
+    Disputed,
+}
+
+/// The results both games of a match reported, as whether they won, that
+/// haven't been matched with the other game's yet.
+#[derive(Default)]
+struct Reports([VecDeque<bool>; 2]);
+impl Reports {
+    /// Adds the result game `n` reported, once the other game reported the
+    /// same match too tells who won it.
+    fn add(&mut self, n: usize, won: bool) -> Option<Verdict> {
+        self.0[n].push_back(won);
+        if self.0.iter().any(VecDeque::is_empty) {
+            return None;
+        }
+        let verdict = match (self.0[0].pop_front()?, self.0[1].pop_front()?) {
+            (true, false) => Verdict::Won(0),
+            (false, true) => Verdict::Won(1),
+            _ => Verdict::Disputed,
+        };
+        Some(verdict)
+    }
+}
+
+struct Server {
+    // the name of the netcode, the server picks it like a host would
+    netcode: &'static str,
+    results: String,
+    // the player waiting for an opponent in each lobby
+    lobbies: Mutex<HashMap<String, Player>>,
+    // matches won by each player since the server started
+    standings: Mutex<HashMap<String, u32>>,
+}
+impl Server {
+    /// Reads the `hello` of a new player and pairs them with the one waiting
+    /// in their lobby, or leaves them waiting there.
+    fn join(self: &Arc<Self>, stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        let mut player = Player {
+            name: String::new(),
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+        let line = player.receive()?;
+        let lobby = match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["hello", name, lobby] => {
+                player.name = name.to_string();
+                lobby.to_string()
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unexpected greeting `{}`", line),
+                ))
+            }
+        };
+        self.enter(lobby, player)
+    }
+
+    /// Starts a match with the player waiting in `lobby`, or leaves `player`
+    /// waiting there when there's nobody.
+    fn enter(self: &Arc<Self>, lobby: String, player: Player) -> io::Result<()> {
+        let name = player.name.clone();
+        let players = pair(&mut self.lobbies.lock().unwrap(), &lobby, player);
+        match players {
+            Some(players) => self.start_match(lobby, players),
+            None => {
+                println!("{} is waiting in lobby {}", name, lobby);
+                Ok(())
+            }
+        }
+    }
+
+    fn start_match(self: &Arc<Self>, lobby: String, mut players: [Player; 2]) -> io::Result<()> {
+        let mut seed = [0; 8];
+        getrandom::getrandom(&mut seed).expect("Could not create RNG seed");
+        let greeting = format!(
+            "tetris {} {} {}",
+            PROTOCOL_VERSION,
+            u64::from_ne_bytes(seed),
+            self.netcode
+        );
+        // the one who was waiting may have left already, the write still
+        // going through then, so the new player only gets the greeting once
+        // they answered it and otherwise waits in their place
+        let waiting = &mut players[0];
+        if let Err(e) = waiting.send(&greeting).and_then(|()| waiting.confirm()) {
+            println!("{} left lobby {}: {}", waiting.name, lobby, e);
+            let [_, player] = players;
+            return self.enter(lobby, player);
+        }
+        players[1].send(&greeting)?;
+        players[1].confirm()?;
+        let names = [players[0].name.clone(), players[1].name.clone()];
+        println!("{} against {} in lobby {}", names[0], names[1], lobby);
+        let reports = Arc::new(Mutex::new(Reports::default()));
+        let [a, b] = players;
+        let writers = [b.writer.try_clone()?, a.writer.try_clone()?];
+        for (n, (player, to)) in [a, b].into_iter().zip(writers).enumerate() {
+            let server = Arc::clone(self);
+            let reports = Arc::clone(&reports);
+            let (lobby, names) = (lobby.clone(), names.clone());
+            thread::spawn(move || {
+                if let Err(e) = server.relay(player, to, n, &reports, &lobby, &names) {
+                    println!("match in lobby {} ended: {}", lobby, e);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Passes what player `n` of a match sends to the other one until one of
+    /// them leaves.
+    fn relay(
+        &self,
+        mut player: Player,
+        mut to: TcpStream,
+        n: usize,
+        reports: &Mutex<Reports>,
+        lobby: &str,
+        names: &[String; 2],
+    ) -> io::Result<()> {
+        let result = self.forward(&mut player, &mut to, n, reports, lobby, names);
+        // once one of them leaves the match is over for both
+        let _ = player.writer.shutdown(Shutdown::Both);
+        let _ = to.shutdown(Shutdown::Both);
+        result
+    }
+
+    // the lines of the match go to the other player, the results to the server
+    fn forward(
+        &self,
+        player: &mut Player,
+        to: &mut TcpStream,
+        n: usize,
+        reports: &Mutex<Reports>,
+        lobby: &str,
+        names: &[String; 2],
+    ) -> io::Result<()> {
+        loop {
+            let line = player.receive()?;
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                ["result", _frame, result] => {
+                    let verdict = reports.lock().unwrap().add(n, result == "won");
+                    match verdict {
+                        Some(Verdict::Won(winner)) => {
+                            self.record(lobby, names, Some(&names[winner]))?
+                        }
+                        Some(Verdict::Disputed) => self.record(lobby, names, None)?,
+                        None => {}
+                    }
+                }
+                _ => writeln!(to, "{}", line)?,
+            }
+        }
+    }
+
+    /// Adds a line to the results file, `None` being a match where the games
+    /// don't agree on who won.
+    fn record(&self, lobby: &str, names: &[String; 2], winner: Option<&String>) -> io::Result<()> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let winner = winner.map_or("disputed", String::as_str);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.results)?;
+        writeln!(
+            file,
+            "{} {} {} {} {}",
+            time, lobby, names[0], names[1], winner
+        )?;
+        let mut standings = self.standings.lock().unwrap();
+        if winner != "disputed" {
+            *standings.entry(winner.to_string()).or_insert(0) += 1;
+        }
+        let mut table: Vec<_> = standings.iter().collect();
+        table.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        println!("{} won in lobby {}, standings:", winner, lobby);
+        for (name, wins) in table {
+            println!("  {} {}", name, wins);
+        }
+        Ok(())
+    }
+}
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let port: u16 = match args.get(1).map(|port| port.parse()) {
+        Some(Ok(port)) => port,
+        _ => {
+            eprintln!("usage: tetris-server <port> [--rollback] [--results <file>]");
+            std::process::exit(2);
+        }
+    };
+    let mut server = Server {
+        netcode: "lockstep",
+        results: "results.txt".to_string(),
+        lobbies: Mutex::new(HashMap::new()),
+        standings: Mutex::new(HashMap::new()),
+    };
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--rollback" => server.netcode = "rollback",
+            "--results" => match options.next() {
+                Some(path) => server.results = path.clone(),
+                None => {
+                    eprintln!("--results needs the path of a file");
+                    std::process::exit(2);
+                }
+            },
+            _ => {
+                eprintln!("unknown option `{}`", option);
+                std::process::exit(2);
+            }
+        }
+    }
+    let server = Arc::new(server);
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    println!("Listening on port {}", port);
+    for stream in listener.incoming() {
+        // a connection failing before it's accepted is only that player's
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("could not accept a player: {}", e);
+                continue;
+            }
+        };
+        let server = Arc::clone(&server);
+        thread::spawn(move || {
+            if let Err(e) = server.join(stream) {
+                println!("{}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairs_players_of_the_same_lobby() {
+        let mut lobbies = HashMap::new();
+        assert_eq!(pair(&mut lobbies, "a", "ann"), None);
+        assert_eq!(pair(&mut lobbies, "b", "bob"), None);
+        assert_eq!(pair(&mut lobbies, "a", "cat"), Some(["ann", "cat"]));
+        assert_eq!(lobbies, HashMap::from([("b".to_string(), "bob")]));
+        // the lobby is free again after a match started
+        assert_eq!(pair(&mut lobbies, "a", "dan"), None);
+        assert_eq!(pair(&mut lobbies, "b", "eve"), Some(["bob", "eve"]));
+        assert_eq!(lobbies, HashMap::from([("a".to_string(), "dan")]));
+    }
+
+    #[test]
+    fn matches_the_results_of_both_games() {
+        let mut reports = Reports::default();
+        assert_eq!(reports.add(0, true), None);
+        assert_eq!(reports.add(1, false), Some(Verdict::Won(0)));
+        assert_eq!(reports.add(1, true), None);
+        assert_eq!(reports.add(0, false), Some(Verdict::Won(1)));
+    }
+
+    #[test]
+    fn disputes_results_that_dont_agree() {
+        let mut reports = Reports::default();
+        assert_eq!(reports.add(0, true), None);
+        assert_eq!(reports.add(1, true), Some(Verdict::Disputed));
+        assert_eq!(reports.add(1, false), None);
+        assert_eq!(reports.add(0, false), Some(Verdict::Disputed));
+    }
+
+    #[test]
+    fn matches_results_in_the_order_of_the_matches() {
+        let mut reports = Reports::default();
+        // one game ran two matches ahead of the other
+        assert_eq!(reports.add(0, true), None);
+        assert_eq!(reports.add(0, false), None);
+        assert_eq!(reports.add(1, false), Some(Verdict::Won(0)));
+        assert_eq!(reports.add(1, false), Some(Verdict::Disputed));
+        assert_eq!(reports.add(1, true), None);
+    }
+}
@@ -0,0 +1,621 @@
+//! `settings.toml`, read from the directory the game runs in: the window, how
+//! held keys repeat, the single player keys and the modifiers a single player
+//! game starts with. Every field can be left out for its default. The file is
+//! checked when the game starts, a mistake stops it with the line and what's
+//! wrong, and it's read again whenever it changes while the game runs, a
+//! mistake then only keeps the settings as they were.
+//!
+//! The window applies to every mode, the handling and the controls to the ones
+//! with a single player on the keyboard: the single player game, the battle
+//! royale and the network matches. In local versus and co-op the players
+//! share the keyboard, each with their own fixed keys, and in a TBP game the
+//! bot plays.
+//!
+//! The frame rate isn't a setting: the gravity, the garbage delay and the
+//! replays count frames, so it's part of the rules. There's no audio section
+//! either, the game has no sound yet.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use ggez::conf::{FullscreenType, WindowMode};
+use ggez::event::{self, Button, GamepadId, KeyCode, KeyMods};
+use ggez::graphics::{self, Rect};
+use ggez::{Context, GameError, GameResult};
+use serde::Deserialize;
+
+use crate::input::KeyBindings;
+use crate::replay::GridSettings;
+use crate::spectate::Watched;
+use crate::{GameState, Grid, StackVisibility, SCREEN_SIZE};
+
+pub const SETTINGS_FILE: &str = "settings.toml";
+// How often the file is looked at for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// Keys the single player game already uses for something else.
+const GAME_KEYS: [(KeyCode, &str); 7] = [
+    (KeyCode::R, "restarts the game"),
+    (KeyCode::P, "pauses the game"),
+    (KeyCode::I, "changes the stack visibility"),
+    (KeyCode::A, "hands the game to the bot"),
+    (KeyCode::B, "switches Big mode"),
+    (KeyCode::M, "mirrors the board"),
+    (KeyCode::G, "turns the board upside down"),
+];
+// The keys that can be bound, by the names they have in the file.
+const KEYS: [KeyCode; 68] = [
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::Space,
+    KeyCode::Return,
+    KeyCode::Tab,
+    KeyCode::LShift,
+    KeyCode::RShift,
+    KeyCode::LControl,
+    KeyCode::RControl,
+    KeyCode::LAlt,
+    KeyCode::RAlt,
+    KeyCode::Comma,
+    KeyCode::Period,
+    KeyCode::Slash,
+    KeyCode::Semicolon,
+    KeyCode::Apostrophe,
+    KeyCode::LBracket,
+    KeyCode::RBracket,
+    KeyCode::Minus,
+    KeyCode::Equals,
+    KeyCode::Numpad0,
+    KeyCode::Numpad1,
+    KeyCode::Numpad2,
+    KeyCode::Numpad3,
+    KeyCode::Numpad4,
+    KeyCode::Numpad5,
+    KeyCode::Numpad6,
+    KeyCode::Numpad7,
+    KeyCode::Numpad8,
+    KeyCode::Numpad9,
+];
+
+#[derive(Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub video: Video,
+    pub handling: Handling,
+    pub controls: Controls,
+    pub gameplay: Gameplay,
+}
+
+/// The window. The game is drawn for 1920 by 1080 and scaled to its size.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Video {
+    pub width: f32,
+    pub height: f32,
+    pub fullscreen: bool,
+    // only read when the game starts
+    pub vsync: bool,
+}
+impl Default for Video {
+    fn default() -> Self {
+        Video {
+            width: SCREEN_SIZE.0,
+            height: SCREEN_SIZE.1,
+            fullscreen: true,
+            vsync: true,
+        }
+    }
+}
+impl Video {
+    pub fn window_mode(&self) -> WindowMode {
+        let fullscreen_type = if self.fullscreen {
+            FullscreenType::True
+        } else {
+            FullscreenType::Windowed
+        };
+        WindowMode::default()
+            .dimensions(self.width, self.height)
+            .borderless(self.fullscreen)
+            .fullscreen_type(fullscreen_type)
+    }
+
+    /// Sets the window to these settings, keeping the game drawn on the whole
+    /// of it.
+    pub fn apply(&self, ctx: &mut Context) -> GameResult {
+        graphics::set_mode(ctx, self.window_mode())?;
+        graphics::set_screen_coordinates(ctx, Rect::new(0.0, 0.0, SCREEN_SIZE.0, SCREEN_SIZE.1))
+    }
+}
+
+/// How a held key repeats, in frames.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Handling {
+    // delayed auto shift: how long left or right is held before the figure
+    // starts sliding
+    pub das: u32,
+    // auto repeat rate: frames between two moves of the sliding figure, 0
+    // takes it to the wall at once
+    pub arr: u32,
+    // frames between two soft drops while the key is held
+    pub soft_drop: u32,
+}
+impl Default for Handling {
+    fn default() -> Self {
+        Handling {
+            das: 10,
+            arr: 2,
+            soft_drop: 2,
+        }
+    }
+}
+
+/// A key named in the file, like `Left`, `C` or `Space`.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(try_from = "String")]
+pub struct Key(pub KeyCode);
+impl TryFrom<String> for Key {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        KEYS.iter()
+            .find(|key| format!("{:?}", key).eq_ignore_ascii_case(&name))
+            .map(|key| Key(*key))
+            .ok_or_else(|| format!("`{}` is not a key", name))
+    }
+}
+
+/// The keys of the single player game.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Controls {
+    pub left: Key,
+    pub right: Key,
+    pub soft_drop: Key,
+    pub rotate: Key,
+    pub hold: Key,
+}
+impl Default for Controls {
+    fn default() -> Self {
+        let keys = KeyBindings::arrows();
+        Controls {
+            left: Key(keys.left),
+            right: Key(keys.right),
+            soft_drop: Key(keys.soft_drop),
+            rotate: Key(keys.rotate),
+            hold: Key(keys.hold),
+        }
+    }
+}
+impl Controls {
+    pub fn bindings(&self) -> KeyBindings {
+        KeyBindings {
+            left: self.left.0,
+            right: self.right.0,
+            soft_drop: self.soft_drop.0,
+            rotate: self.rotate.0,
+            hold: self.hold.0,
+        }
+    }
+}
+
+/// How a single player game starts.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Gameplay {
+    pub big: bool,
+    pub mirrored: bool,
+    pub upside_down: bool,
+    pub visibility: StackVisibility,
+}
+impl Default for Gameplay {
+    fn default() -> Self {
+        Gameplay {
+            big: false,
+            mirrored: false,
+            upside_down: false,
+            visibility: StackVisibility::Visible,
+        }
+    }
+}
+impl Gameplay {
+    pub fn grid(&self) -> Grid {
+        GridSettings {
+            big: self.big,
+            mirrored: self.mirrored,
+            upside_down: self.upside_down,
+        }
+        .grid()
+    }
+}
+
+impl Settings {
+    /// Reads the settings at `path`, the defaults when there's no file.
+    pub fn load<P: AsRef<Path>>(path: P) -> GameResult<Settings> {
+        let path = path.as_ref();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Settings::default()),
+            Err(e) => return Err(e.into()),
+        };
+        Settings::parse(&text)
+            .map_err(|e| GameError::ConfigError(format!("{}: {}", path.display(), e)))
+    }
+
+    fn parse(text: &str) -> Result<Settings, String> {
+        let settings: Settings = toml::from_str(text).map_err(|e| e.to_string())?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let Video { width, height, .. } = self.video;
+        if !(320.0..=7680.0).contains(&width) || !(240.0..=4320.0).contains(&height) {
+            return Err(format!(
+                "video: {}x{} is not a window size, it goes from 320x240 to 7680x4320",
+                width, height
+            ));
+        }
+        let Handling {
+            das,
+            arr,
+            soft_drop,
+        } = self.handling;
+        for (name, frames, range) in [
+            ("das", das, 0..=60),
+            ("arr", arr, 0..=30),
+            ("soft_drop", soft_drop, 1..=30),
+        ] {
+            if !range.contains(&frames) {
+                return Err(format!(
+                    "handling: {} is {} frames, it goes from {} to {}",
+                    name,
+                    frames,
+                    range.start(),
+                    range.end()
+                ));
+            }
+        }
+        let controls = self.controls;
+        let keys = [
+            ("left", controls.left),
+            ("right", controls.right),
+            ("soft_drop", controls.soft_drop),
+            ("rotate", controls.rotate),
+            ("hold", controls.hold),
+        ];
+        for (n, (action, key)) in keys.iter().enumerate() {
+            if let Some((other, _)) = keys[..n].iter().find(|(_, other)| other == key) {
+                return Err(format!(
+                    "controls: {} and {} both use {:?}",
+                    other, action, key.0
+                ));
+            }
+            if let Some((_, what)) = GAME_KEYS.iter().find(|(game_key, _)| *game_key == key.0) {
+                return Err(format!(
+                    "controls: {} can't use {:?}, it {}",
+                    action, key.0, what
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Tells when a file was changed since it was last looked at.
+struct Watcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    checked: Instant,
+}
+impl Watcher {
+    fn new(path: PathBuf) -> Self {
+        let modified = Watcher::modified(&path);
+        Watcher {
+            path,
+            modified,
+            checked: Instant::now(),
+        }
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|meta| meta.modified()).ok()
+    }
+
+    fn changed(&mut self) -> bool {
+        if self.checked.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.checked = Instant::now();
+        let modified = Watcher::modified(&self.path);
+        let changed = modified != self.modified;
+        self.modified = modified;
+        changed
+    }
+}
+
+/// A game that takes the settings that concern it. `Configured` sets the
+/// window itself, so the modes the other settings don't concern do nothing.
+pub trait Configurable {
+    fn configure(&mut self, _settings: &Settings) {}
+}
+
+/// Runs `handler` with the settings, reading them again when the file changes.
+pub struct Configured<H> {
+    handler: H,
+    settings: Settings,
+    watcher: Watcher,
+}
+impl<H: Configurable> Configured<H> {
+    pub fn new(mut handler: H, settings: Settings) -> Self {
+        handler.configure(&settings);
+        Configured {
+            handler,
+            settings,
+            watcher: Watcher::new(PathBuf::from(SETTINGS_FILE)),
+        }
+    }
+
+    fn reload(&mut self, ctx: &mut Context) -> GameResult {
+        let video = self.settings.video;
+        if self.read() && self.settings.video != video {
+            self.settings.video.apply(ctx)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the file again and hands the settings to the game, unless there's
+    /// a mistake in it, then they stay as they were. Tells whether they were
+    /// read.
+    fn read(&mut self) -> bool {
+        let settings = match Settings::load(&self.watcher.path) {
+            Ok(settings) => settings,
+            Err(e) => {
+                eprintln!("settings not reloaded, {}", e);
+                return false;
+            }
+        };
+        self.handler.configure(&settings);
+        self.settings = settings;
+        println!("settings reloaded");
+        true
+    }
+}
+
+impl<H: event::EventHandler<GameError> + Configurable> event::EventHandler<GameError>
+    for Configured<H>
+{
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        if self.watcher.changed() {
+            self.reload(ctx)?;
+        }
+        self.handler.update(ctx)
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.handler.draw(ctx)
+    }
+
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        keycode: KeyCode,
+        keymod: KeyMods,
+        repeat: bool,
+    ) {
+        self.handler.key_down_event(ctx, keycode, keymod, repeat);
+    }
+
+    fn key_up_event(&mut self, ctx: &mut Context, keycode: KeyCode, keymod: KeyMods) {
+        self.handler.key_up_event(ctx, keycode, keymod);
+    }
+
+    fn text_input_event(&mut self, ctx: &mut Context, character: char) {
+        self.handler.text_input_event(ctx, character);
+    }
+
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, btn: Button, id: GamepadId) {
+        self.handler.gamepad_button_down_event(ctx, btn, id);
+    }
+
+    fn quit_event(&mut self, ctx: &mut Context) -> bool {
+        self.handler.quit_event(ctx)
+    }
+}
+
+impl<H: Watched> Watched for Configured<H> {
+    fn boards(&self) -> Vec<&GameState> {
+        self.handler.boards()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_settings_of_the_repo() {
+        let text = fs::read_to_string(SETTINGS_FILE).unwrap();
+        assert_eq!(Settings::parse(&text), Ok(Settings::default()));
+        assert_eq!(Settings::parse(""), Ok(Settings::default()));
+    }
+
+    #[test]
+    fn reads_the_fields_given() {
+        let settings = Settings::parse(
+            "[handling]\ndas = 6\narr = 0\n[controls]\nleft = \"j\"\nhold = \"LShift\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            settings.handling,
+            Handling {
+                das: 6,
+                arr: 0,
+                ..Handling::default()
+            }
+        );
+        assert_eq!(settings.controls.left, Key(KeyCode::J));
+        assert_eq!(settings.controls.hold, Key(KeyCode::LShift));
+        assert_eq!(settings.controls.rotate, Controls::default().rotate);
+    }
+
+    // a file of its own for each test, in the temporary directory
+    fn settings_file(name: &str, text: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("tetris-{}-{}.toml", name, std::process::id()));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    // sets when `path` was last changed, to tell changes apart without waiting
+    fn touch(path: &Path, seconds: u64) {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    #[test]
+    fn watches_for_changes() {
+        let path = settings_file("watched", "");
+        touch(&path, 1000);
+        let mut watcher = Watcher::new(path.clone());
+        watcher.checked -= POLL_INTERVAL;
+        assert!(!watcher.changed());
+        touch(&path, 2000);
+        // not looked at again before the interval
+        assert!(!watcher.changed());
+        watcher.checked -= POLL_INTERVAL;
+        assert!(watcher.changed());
+        watcher.checked -= POLL_INTERVAL;
+        assert!(!watcher.changed());
+        fs::remove_file(&path).unwrap();
+        watcher.checked -= POLL_INTERVAL;
+        assert!(watcher.changed());
+    }
+
+    // the settings a game was given
+    #[derive(Default)]
+    struct Given(Vec<Settings>);
+    impl Configurable for Given {
+        fn configure(&mut self, settings: &Settings) {
+            self.0.push(settings.clone());
+        }
+    }
+
+    #[test]
+    fn keeps_the_settings_when_the_file_has_a_mistake() {
+        let path = settings_file("reloaded", "[handling]\ndas = 4\n");
+        let mut configured = Configured::new(Given::default(), Settings::default());
+        configured.watcher = Watcher::new(path.clone());
+        assert!(configured.read());
+        assert_eq!(configured.settings.handling.das, 4);
+        fs::write(&path, "[handling]\ndas = 99\n").unwrap();
+        assert!(!configured.read());
+        fs::write(&path, "[handling\n").unwrap();
+        assert!(!configured.read());
+        assert_eq!(configured.settings.handling.das, 4);
+        let given: Vec<u32> = configured
+            .handler
+            .0
+            .iter()
+            .map(|settings| settings.handling.das)
+            .collect();
+        assert_eq!(given, [10, 4]);
+        fs::remove_file(&path).unwrap();
+        // no file is the defaults
+        assert!(configured.read());
+        assert_eq!(configured.settings, Settings::default());
+    }
+
+    #[test]
+    fn rejects_bad_settings() {
+        for (text, error) in [
+            (
+                "[video]\nwidth = 100.0",
+                "video: 100x1080 is not a window size, it goes from 320x240 to 7680x4320",
+            ),
+            (
+                "[video]\nheight = 5000.0",
+                "video: 1920x5000 is not a window size, it goes from 320x240 to 7680x4320",
+            ),
+            (
+                "[handling]\ndas = 61",
+                "handling: das is 61 frames, it goes from 0 to 60",
+            ),
+            (
+                "[handling]\narr = 31",
+                "handling: arr is 31 frames, it goes from 0 to 30",
+            ),
+            (
+                "[handling]\nsoft_drop = 0",
+                "handling: soft_drop is 0 frames, it goes from 1 to 30",
+            ),
+            (
+                "[controls]\nhold = \"Up\"",
+                "controls: rotate and hold both use Up",
+            ),
+            (
+                "[controls]\nleft = \"A\"\nright = \"D\"",
+                "controls: left can't use A, it hands the game to the bot",
+            ),
+            (
+                "[controls]\nrotate = \"P\"",
+                "controls: rotate can't use P, it pauses the game",
+            ),
+        ] {
+            assert_eq!(Settings::parse(text), Err(error.to_string()), "{}", text);
+        }
+        // the mistakes toml finds come with their line
+        for text in [
+            "[controls]\nleft = \"Escape\"",
+            "[handling]\ndas = -1",
+            "[video]\nfullscreen = \"yes\"",
+            "[audio]\nvolume = 3",
+            "[handling]\ndas = 4\ndas = 5",
+        ] {
+            let error = Settings::parse(text).unwrap_err();
+            assert!(error.contains("line"), "{}: {}", text, error);
+        }
+    }
+}
@@ -0,0 +1,527 @@
+//! Spectators: a game started with `--spectators <port>` streams what's on its
+//! boards to everyone connected to that port, and `tetris --spectate <address>`
+//! shows that stream with the same drawing code the players see, without
+//! taking any input.
+//!
+//! Every time a board changes the game sends one line with all of them, `|`
+//! between boards. Each board is
+//!
+//! ```text
+//! <grid> <score> <state> <garbage> <hold> <queue> <piece> <stack>
+//! ```
+//!
+//! where `<grid>` is `N`, `B` (Big mode) or `W` (the wide co-op board) followed
+//! by `M` when mirrored and `U` when upside down, `<state>` is `playing` or
+//! `over`, `<garbage>` the lines waiting to come up, `<hold>` and `<queue>`
+//! piece letters (`-` for none), `<piece>` the falling piece as
+//! `T@x,y;x,y;x,y;x,y` (or `-`) and `<stack>` the
+//! rows from the top, `/` between rows, with `.` for empty cells, `X` for
+//! garbage and piece letters for the rest. On a co-op board every player has
+//! their own `<hold>`, `<queue>` and `<piece>`, with `+` between the players'.
+//! Spectators always see the whole stack, even in the invisible modes.
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread;
+use std::time::Duration;
+
+use ggez::event::{self, Button, GamepadId, KeyCode, KeyMods};
+use ggez::graphics::{self, Color, DrawParam, Font, PxScale, Text};
+use ggez::{timer, Context, GameError, GameResult};
+use mint::Point2;
+
+use oorandom::Rand32;
+
+use crate::{
+    Block, Figure, GameState, Grid, Layout, PieceType, Seat, GARBAGE_COLOR, GRID_WIDTH, SCREEN_SIZE,
+};
+
+// Lines waiting to be written to a spectator, the ones a spectator falls
+// further behind by are skipped. The game doesn't wait for them.
+const BACKLOG: usize = 4;
+// A spectator that can't take a line for this long is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A game mode that can be watched, as the boards it has on screen.
+pub trait Watched {
+    fn boards(&self) -> Vec<&GameState>;
+}
+impl Watched for GameState {
+    fn boards(&self) -> Vec<&GameState> {
+        vec![self]
+    }
+}
+
+/// The line sent to the spectators for `boards`.
+pub fn encode_view(boards: &[&GameState]) -> String {
+    boards
+        .iter()
+        .map(|board| encode_board(board))
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+pub fn decode_view(line: &str) -> Result<Vec<GameState>, String> {
+    line.split('|')
+        .map(|board| decode_board(board.trim()))
+        .collect()
+}
+
+fn encode_board(board: &GameState) -> String {
+    let mut grid = String::from(if board.grid.is_big() {
+        "B"
+    } else if board.grid.width > GRID_WIDTH {
+        "W"
+    } else {
+        "N"
+    });
+    if board.grid.mirrored {
+        grid.push('M');
+    }
+    if board.grid.upside_down {
+        grid.push('U');
+    }
+    let state = if board.gameover { "over" } else { "playing" };
+    let seats: Vec<[String; 3]> = board.seats.iter().map(encode_seat).collect();
+    let field = |i: usize| {
+        seats
+            .iter()
+            .map(|seat| seat[i].as_str())
+            .collect::<Vec<_>>()
+            .join("+")
+    };
+    let mut rows = vec![vec!['.'; board.grid.width as usize]; board.grid.height as usize];
+    for block in board.static_blocks.iter() {
+        if let Some(cell) = rows
+            .get_mut(block.y as usize)
+            .and_then(|row| row.get_mut(block.x as usize))
+        {
+            *cell = cell_letter(block.color);
+        }
+    }
+    let stack: Vec<String> = rows.into_iter().map(String::from_iter).collect();
+    format!(
+        "{} {} {} {} {} {} {} {}",
+        grid,
+        board.score,
+        state,
+        board.garbage.pending(),
+        field(0),
+        field(1),
+        field(2),
+        stack.join("/")
+    )
+}
+
+// the hold, queue and piece fields of a seat
+fn encode_seat(seat: &Seat) -> [String; 3] {
+    let hold = seat
+        .keep_figure
+        .map_or('-', |fig| fig.piece_type.letter())
+        .to_string();
+    let queue: String = if seat.next_figures.is_empty() {
+        "-".to_string()
+    } else {
+        seat.next_figures
+            .iter()
+            .map(|fig| fig.piece_type.letter())
+            .collect()
+    };
+    let piece = match &seat.actual_figure {
+        Some(fig) => {
+            let blocks: Vec<String> = fig
+                .blocks
+                .iter()
+                .map(|block| format!("{},{}", block.x, block.y))
+                .collect();
+            format!("{}@{}", fig.piece_type.letter(), blocks.join(";"))
+        }
+        None => "-".to_string(),
+    };
+    [hold, queue, piece]
+}
+
+fn decode_board(text: &str) -> Result<GameState, String> {
+    let fields: Vec<&str> = text.split_whitespace().collect();
+    let [grid, score, state, garbage, hold, queue, piece, stack] = fields[..] else {
+        return Err(format!("expected 8 fields in `{}`", text));
+    };
+    let mut board = GameState::with_seed(0);
+    board.grid = match grid.chars().next() {
+        Some('N') => Grid::normal(),
+        Some('B') => Grid::big(),
+        Some('W') => Grid::wide(),
+        _ => return Err(format!("unknown grid `{}`", grid)),
+    };
+    board.grid.mirrored = grid.contains('M');
+    board.grid.upside_down = grid.contains('U');
+    board.score = score
+        .parse()
+        .map_err(|_| format!("`{}` is not a score", score))?;
+    board.gameover = match state {
+        "playing" => false,
+        "over" => true,
+        _ => return Err(format!("unknown state `{}`", state)),
+    };
+    let garbage = garbage
+        .parse()
+        .map_err(|_| format!("`{}` is not a line count", garbage))?;
+    board.garbage.receive(garbage, 0);
+    let holds: Vec<&str> = hold.split('+').collect();
+    let queues: Vec<&str> = queue.split('+').collect();
+    let pieces: Vec<&str> = piece.split('+').collect();
+    if queues.len() != holds.len() || pieces.len() != holds.len() {
+        return Err(format!(
+            "every player needs a hold, a queue and a piece in `{} {} {}`",
+            hold, queue, piece
+        ));
+    }
+    board.seats = holds
+        .iter()
+        .zip(queues)
+        .zip(pieces)
+        .map(|((hold, queue), piece)| decode_seat(hold, queue, piece))
+        .collect::<Result<_, _>>()?;
+    let rows: Vec<&str> = stack.split('/').collect();
+    if rows.len() != board.grid.height as usize {
+        return Err(format!("the stack must have {} rows", board.grid.height));
+    }
+    for (y, row) in rows.iter().enumerate() {
+        if row.chars().count() != board.grid.width as usize {
+            return Err(format!(
+                "stack row `{}` must be {} cells wide",
+                row, board.grid.width
+            ));
+        }
+        for (x, cell) in row.chars().enumerate() {
+            let color = match cell {
+                '.' => continue,
+                'X' => GARBAGE_COLOR,
+                letter => decode_figure(&letter.to_string())?.blocks[0].color,
+            };
+            board
+                .static_blocks
+                .push(Block::new(x as i16, y as i16, color));
+        }
+    }
+    board.stack_changed();
+    Ok(board)
+}
+
+fn decode_seat(hold: &str, queue: &str, piece: &str) -> Result<Seat, String> {
+    let mut seat = Seat::new(&mut Rand32::new(0), 0);
+    seat.keep_figure = match hold {
+        "-" => None,
+        letter => Some(decode_figure(letter)?),
+    };
+    seat.next_figures = match queue {
+        "-" => vec![],
+        letters => decode_figures(letters)?,
+    };
+    seat.actual_figure = match piece.split_once('@') {
+        None if piece == "-" => None,
+        Some((letter, blocks)) => {
+            let mut fig = decode_figure(letter)?;
+            let blocks: Vec<&str> = blocks.split(';').collect();
+            if blocks.len() != fig.blocks.len() {
+                return Err(format!("`{}` doesn't have 4 blocks", piece));
+            }
+            for (block, coords) in fig.blocks.iter_mut().zip(blocks) {
+                let (x, y) = coords
+                    .split_once(',')
+                    .and_then(|(x, y)| Some((x.parse().ok()?, y.parse().ok()?)))
+                    .ok_or(format!("`{}` is not a position", coords))?;
+                block.x = x;
+                block.y = y;
+            }
+            Some(fig)
+        }
+        None => return Err(format!("unknown piece `{}`", piece)),
+    };
+    Ok(seat)
+}
+
+fn decode_figures(letters: &str) -> Result<Vec<Figure>, String> {
+    letters
+        .chars()
+        .map(|letter| {
+            PieceType::from_letter(letter)
+                .map(|piece| Figure::new(piece.number()))
+                .ok_or(format!("unknown piece `{}`", letter))
+        })
+        .collect()
+}
+
+fn decode_figure(letter: &str) -> Result<Figure, String> {
+    match decode_figures(letter)?[..] {
+        [fig] => Ok(fig),
+        _ => Err(format!("`{}` is not a piece", letter)),
+    }
+}
+
+/// The letter of the piece a block of the stack came from, `X` for garbage.
+pub fn cell_letter(color: Color) -> char {
+    "TOILJZS"
+        .chars()
+        .find(|letter| {
+            let piece = PieceType::from_letter(*letter).unwrap();
+            Figure::new(piece.number()).blocks[0].color == color
+        })
+        .unwrap_or('X')
+}
+
+/// Runs a game mode while streaming its boards to the spectators.
+pub struct Broadcast<H> {
+    handler: H,
+    listener: TcpListener,
+    // the lines for each spectator, written on their own thread
+    spectators: Vec<SyncSender<String>>,
+    // the last line sent, a new spectator gets it right away
+    last: String,
+}
+impl<H: Watched> Broadcast<H> {
+    pub fn new(handler: H, port: u16) -> GameResult<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        println!("Spectators can watch on port {}", port);
+        Ok(Broadcast {
+            handler,
+            listener,
+            spectators: vec![],
+            last: String::new(),
+        })
+    }
+
+    fn send(&mut self) -> GameResult {
+        let line = encode_view(&self.handler.boards());
+        if line != self.last {
+            self.spectators.retain(|spectator| {
+                !matches!(
+                    spectator.try_send(line.clone()),
+                    Err(TrySendError::Disconnected(_))
+                )
+            });
+            self.last = line;
+        }
+        loop {
+            match self.listener.accept() {
+                Ok((spectator, _)) => {
+                    spectator.set_nonblocking(false)?;
+                    spectator.set_nodelay(true)?;
+                    spectator.set_write_timeout(Some(WRITE_TIMEOUT))?;
+                    let lines = write_lines(spectator);
+                    if lines.try_send(self.last.clone()).is_ok() {
+                        self.spectators.push(lines);
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Writes the lines sent to the channel given back to `spectator`, until it
+/// fails to take one.
+fn write_lines(mut spectator: TcpStream) -> SyncSender<String> {
+    let (sender, lines) = mpsc::sync_channel::<String>(BACKLOG);
+    thread::spawn(move || {
+        for line in lines {
+            if writeln!(spectator, "{}", line).is_err() {
+                break;
+            }
+        }
+    });
+    sender
+}
+
+impl<H: event::EventHandler<GameError> + Watched> event::EventHandler<GameError> for Broadcast<H> {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        self.handler.update(ctx)?;
+        self.send()
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.handler.draw(ctx)
+    }
+
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        keycode: KeyCode,
+        keymod: KeyMods,
+        repeat: bool,
+    ) {
+        self.handler.key_down_event(ctx, keycode, keymod, repeat);
+    }
+
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, btn: Button, id: GamepadId) {
+        self.handler.gamepad_button_down_event(ctx, btn, id);
+    }
+
+    fn key_up_event(&mut self, ctx: &mut Context, keycode: KeyCode, keymod: KeyMods) {
+        self.handler.key_up_event(ctx, keycode, keymod);
+    }
+
+    fn text_input_event(&mut self, ctx: &mut Context, character: char) {
+        self.handler.text_input_event(ctx, character);
+    }
+
+    fn quit_event(&mut self, ctx: &mut Context) -> bool {
+        self.handler.quit_event(ctx)
+    }
+}
+
+/// Watches the game streamed at some address. Nothing is played here, the
+/// boards are only drawn.
+pub struct Spectator {
+    reader: BufReader<TcpStream>,
+    // the start of a line that didn't fully arrive yet
+    partial: Vec<u8>,
+    boards: Vec<GameState>,
+}
+impl Spectator {
+    pub fn connect(addr: &str) -> GameResult<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nonblocking(true)?;
+        Ok(Spectator {
+            reader: BufReader::new(stream),
+            partial: vec![],
+            boards: vec![],
+        })
+    }
+}
+
+impl event::EventHandler<GameError> for Spectator {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        // only the last complete line matters, the ones before are already old
+        loop {
+            match self.reader.read_until(b'\n', &mut self.partial) {
+                Ok(0) => {
+                    return Err(GameError::CustomError("the game is over".to_string()));
+                }
+                Ok(_) if self.partial.ends_with(b"\n") => {
+                    let line = String::from_utf8_lossy(&self.partial).trim().to_string();
+                    self.partial.clear();
+                    if !line.is_empty() {
+                        self.boards = decode_view(&line).map_err(GameError::CustomError)?;
+                    }
+                }
+                Ok(_) => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
+        match self.boards.len() {
+            0 => {
+                let mut text = Text::new("Waiting for the game...");
+                text.set_font(Font::default(), PxScale::from(48.0));
+                let width = text.width(ctx);
+                graphics::draw(
+                    ctx,
+                    &text,
+                    DrawParam::default().dest(Point2 {
+                        x: (SCREEN_SIZE.0 - width) / 2.0,
+                        y: SCREEN_SIZE.1 / 2.0,
+                    }),
+                )?;
+            }
+            1 => self.boards[0].draw_board(ctx, &Layout::single())?,
+            _ => {
+                for (n, board) in self.boards.iter().enumerate() {
+                    board.draw_board(ctx, &Layout::versus(n))?;
+                }
+            }
+        }
+        graphics::present(ctx)?;
+        timer::yield_now();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Input;
+
+    struct Boards(Vec<GameState>);
+    impl Watched for Boards {
+        fn boards(&self) -> Vec<&GameState> {
+            self.0.iter().collect()
+        }
+    }
+
+    #[test]
+    fn views_round_trip() {
+        let mut board = GameState::with_seed(3);
+        for input in [Input::Rotate, Input::Left, Input::Hold] {
+            board.handle_input(input);
+            for _ in 0..20 {
+                board.handle_input(Input::SoftDrop);
+                board.tick();
+            }
+        }
+        board.garbage.receive(2, 0);
+        let mut mirrored = GameState::with_seed(4);
+        mirrored.grid.mirrored = true;
+        mirrored.grid.upside_down = true;
+        mirrored.gameover = true;
+        let line = encode_view(&[&board, &mirrored]);
+        let decoded = decode_view(&line).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].grid, board.grid);
+        assert_eq!(decoded[1].grid, mirrored.grid);
+        assert!(decoded[1].gameover);
+        assert_eq!(decoded[0].garbage.pending(), 2);
+        let cells = |board: &GameState| {
+            let mut cells: Vec<(i16, i16, char)> = board
+                .static_blocks
+                .iter()
+                .map(|block| (block.x, block.y, cell_letter(block.color)))
+                .collect();
+            cells.sort();
+            cells
+        };
+        assert_eq!(cells(&decoded[0]), cells(&board));
+        assert!(!board.static_blocks.is_empty());
+        for (decoded, seat) in decoded[0].seats.iter().zip(board.seats.iter()) {
+            assert_eq!(decoded.actual_figure, seat.actual_figure);
+            assert_eq!(decoded.keep_figure, seat.keep_figure);
+            let letters = |seat: &Seat| encode_seat(seat)[1].clone();
+            assert_eq!(letters(decoded), letters(seat));
+        }
+        // the decoded boards give the same line again
+        let again: Vec<&GameState> = decoded.iter().collect();
+        assert_eq!(encode_view(&again), line);
+        assert!(decode_view("N 0 playing 0 - - -").is_err());
+        assert!(
+            decode_view(&line.replace("playing", "paused")).is_err(),
+            "{}",
+            line
+        );
+    }
+
+    #[test]
+    fn slow_spectators_dont_hold_the_game_up() {
+        let mut broadcast = Broadcast::new(Boards(vec![GameState::with_seed(5)]), 0).unwrap();
+        let port = broadcast.listener.local_addr().unwrap().port();
+        // one spectator never takes the lines it's sent, the other one does
+        let (stalled, backlog) = mpsc::sync_channel(BACKLOG);
+        broadcast.spectators.push(stalled);
+        let mut reader = BufReader::new(TcpStream::connect(("127.0.0.1", port)).unwrap());
+        for score in 0..5000 {
+            broadcast.handler.0[0].score = score;
+            broadcast.send().unwrap();
+        }
+        // the lines that didn't fit were dropped instead of waiting for room
+        assert_eq!(backlog.try_iter().count(), BACKLOG);
+        assert_eq!(broadcast.spectators.len(), 2);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(decode_view(line.trim()).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,487 @@
+//! External bots through the Tetris Bot Protocol: `tetris --tbp <command>`
+//! runs the bot as a child process and talks to it with one JSON message per
+//! line over its stdin and stdout. The game sends it the board, the queue and
+//! the kept piece, asks for a move every time a new piece comes and plays the
+//! first suggested move it can reach with the same inputs a player would press.
+//!
+//! Only the base protocol is spoken, with the `randomizer` extension to tell
+//! the bot the pieces are picked at random one by one. Moves are given in the
+//! protocol's own coordinates, `x` from the left and `y` from the bottom of a
+//! board 40 rows tall, and the piece shapes are the SRS ones.
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ggez::event::{self, KeyCode, KeyMods};
+use ggez::graphics;
+use ggez::{timer, Context, GameError, GameResult};
+use serde::{Deserialize, Serialize};
+
+use crate::bot::{inputs_to, landing};
+use crate::input::Input;
+use crate::settings::Configurable;
+use crate::spectate::{cell_letter, Watched};
+use crate::{Figure, GameState, Grid, Layout, FPS};
+
+// Rows of the board sent to the bot, the ones over ours are always empty.
+const BOARD_ROWS: usize = 40;
+// How long the bot has to introduce itself and accept the rules.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+// Frames between two inputs, so the moves can be followed on screen.
+const DELAY: u32 = 4;
+// How long the bot has to exit after `quit` before it's killed.
+const QUIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Messages the game sends to the bot.
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum FrontendMessage {
+    Rules {
+        randomizer: &'static str,
+    },
+    Start {
+        hold: Option<char>,
+        // the falling piece first
+        queue: Vec<char>,
+        combo: u32,
+        back_to_back: bool,
+        // rows from the bottom, `G` for garbage
+        board: Vec<[Option<char>; 10]>,
+    },
+    Stop,
+    Suggest,
+    Play {
+        #[serde(rename = "move")]
+        mv: Move,
+    },
+    NewPiece {
+        piece: char,
+    },
+    Quit,
+}
+
+/// Messages the bot sends to the game, the ones not known are skipped.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BotMessage {
+    Info {
+        name: String,
+        version: String,
+    },
+    Ready,
+    Error {
+        reason: String,
+    },
+    Suggestion {
+        moves: Vec<Move>,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct Move {
+    location: Location,
+    spin: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+struct Location {
+    #[serde(rename = "type")]
+    piece: char,
+    orientation: Orientation,
+    x: i16,
+    y: i16,
+}
+impl Location {
+    /// The cells of the piece on `grid`, in the game's coordinates.
+    fn cells(&self, grid: Grid) -> Option<[(i16, i16); 4]> {
+        let offsets = srs_offsets(self.piece)?;
+        Some(offsets.map(|offset| {
+            let (dx, dy) = self.orientation.turn(offset);
+            (self.x + dx, grid.height - 1 - (self.y + dy))
+        }))
+    }
+
+    /// Where `fig` lies on `grid`, as the protocol says it.
+    fn of(fig: &Figure, grid: Grid) -> Option<Location> {
+        let piece = fig.piece_type.letter();
+        let mut cells = fig.blocks.map(|block| (block.x, block.y));
+        cells.sort();
+        for orientation in Orientation::ALL {
+            // the center is one of the blocks, except for the I and the O
+            // where it can be next to them
+            for block in fig.blocks.iter() {
+                for (dx, dy) in [(0, 0), (-1, 0), (0, -1), (-1, -1)] {
+                    let location = Location {
+                        piece,
+                        orientation,
+                        x: block.x + dx,
+                        y: grid.height - 1 - block.y + dy,
+                    };
+                    let mut found = location.cells(grid)?;
+                    found.sort();
+                    if found == cells {
+                        return Some(location);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum Orientation {
+    North,
+    East,
+    South,
+    West,
+}
+impl Orientation {
+    const ALL: [Orientation; 4] = [
+        Orientation::North,
+        Orientation::East,
+        Orientation::South,
+        Orientation::West,
+    ];
+
+    /// Turns an offset of the north orientation clockwise into this one.
+    fn turn(&self, (x, y): (i16, i16)) -> (i16, i16) {
+        match *self {
+            Orientation::North => (x, y),
+            Orientation::East => (y, -x),
+            Orientation::South => (-x, -y),
+            Orientation::West => (-y, x),
+        }
+    }
+}
+
+/// The cells of `piece` pointing north around its center, `y` going up.
+fn srs_offsets(piece: char) -> Option<[(i16, i16); 4]> {
+    match piece {
+        'I' => Some([(-1, 0), (0, 0), (1, 0), (2, 0)]),
+        'O' => Some([(0, 0), (1, 0), (0, 1), (1, 1)]),
+        'T' => Some([(-1, 0), (0, 0), (1, 0), (0, 1)]),
+        'L' => Some([(-1, 0), (0, 0), (1, 0), (1, 1)]),
+        'J' => Some([(-1, 0), (0, 0), (1, 0), (-1, 1)]),
+        'S' => Some([(-1, 0), (0, 0), (0, 1), (1, 1)]),
+        'Z' => Some([(-1, 1), (0, 1), (0, 0), (1, 0)]),
+        _ => None,
+    }
+}
+
+fn bot_error(message: String) -> GameError {
+    GameError::CustomError(format!("TBP bot: {}", message))
+}
+
+/// A bot running in its own process.
+pub struct TbpBot {
+    process: Child,
+    stdin: ChildStdin,
+    // the lines the bot writes, read on their own thread
+    lines: Receiver<String>,
+    pub name: String,
+    // pieces of the board the bot was told about, as `Seat::pieces`
+    revealed: u64,
+    // `Seat::pieces` while the suggested move is played, a new one is asked
+    // for once the board gets past it
+    playing: Option<u64>,
+    // a suggestion was asked for and didn't come yet
+    waiting: bool,
+    plan: VecDeque<Input>,
+    wait: u32,
+}
+impl TbpBot {
+    /// Runs `command` and waits for the bot to accept the rules.
+    pub fn spawn(command: &[String]) -> GameResult<Self> {
+        let (program, args) = command
+            .split_first()
+            .ok_or_else(|| bot_error("no command to run".to_string()))?;
+        let mut process = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = process.stdin.take().unwrap();
+        let stdout = BufReader::new(process.stdout.take().unwrap());
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in stdout.lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut bot = TbpBot {
+            process,
+            stdin,
+            lines,
+            name: String::new(),
+            revealed: 0,
+            playing: None,
+            waiting: false,
+            plan: VecDeque::new(),
+            wait: DELAY,
+        };
+        match bot.receive()? {
+            BotMessage::Info { name, version } => bot.name = format!("{} {}", name, version),
+            message => return Err(bot_error(format!("expected info, got {:?}", message))),
+        }
+        bot.send(&FrontendMessage::Rules {
+            randomizer: "uniform",
+        })?;
+        match bot.receive()? {
+            BotMessage::Ready => Ok(bot),
+            BotMessage::Error { reason } => Err(bot_error(reason)),
+            message => Err(bot_error(format!("expected ready, got {:?}", message))),
+        }
+    }
+
+    fn send(&mut self, message: &FrontendMessage) -> GameResult {
+        let line = serde_json::to_string(message).map_err(|e| bot_error(e.to_string()))?;
+        writeln!(self.stdin, "{}", line)?;
+        Ok(self.stdin.flush()?)
+    }
+
+    // waits for the next message of the handshake
+    fn receive(&mut self) -> GameResult<BotMessage> {
+        match self.lines.recv_timeout(HANDSHAKE_TIMEOUT) {
+            Ok(line) => decode(&line),
+            Err(RecvTimeoutError::Timeout) => Err(bot_error("didn't answer".to_string())),
+            Err(RecvTimeoutError::Disconnected) => Err(bot_error("quit".to_string())),
+        }
+    }
+
+    /// Tells the bot about a new game on `board`.
+    pub fn start(&mut self, board: &GameState) -> GameResult {
+        let seat = &board.seats[0];
+        let mut rows = vec![[None; 10]; BOARD_ROWS];
+        for block in board.static_blocks.iter() {
+            let row = (board.grid.height - 1 - block.y) as usize;
+            if let Some(cell) = rows
+                .get_mut(row)
+                .and_then(|row| row.get_mut(block.x as usize))
+            {
+                *cell = Some(match cell_letter(block.color) {
+                    'X' => 'G',
+                    letter => letter,
+                });
+            }
+        }
+        let queue = seat
+            .actual_figure
+            .iter()
+            .chain(seat.next_figures.iter())
+            .map(|fig| fig.piece_type.letter())
+            .collect();
+        self.send(&FrontendMessage::Start {
+            hold: seat.keep_figure.map(|fig| fig.piece_type.letter()),
+            queue,
+            combo: board.garbage.combo(),
+            back_to_back: board.garbage.back_to_back(),
+            board: rows,
+        })?;
+        self.revealed = seat.pieces;
+        self.playing = None;
+        self.waiting = false;
+        self.plan.clear();
+        Ok(())
+    }
+
+    /// Tells the bot the game it was playing is over.
+    pub fn stop(&mut self) -> GameResult {
+        self.send(&FrontendMessage::Stop)
+    }
+
+    /// A move was asked for and the bot didn't suggest one yet. A game with
+    /// no clock to keep can wait for it before playing the next frame.
+    pub fn thinking(&self) -> bool {
+        self.waiting
+    }
+
+    /// What the bot presses on this frame of `board`, once it's done moving
+    /// the piece it keeps dropping it like the built-in bot.
+    pub fn input(&mut self, board: &GameState) -> GameResult<Option<Input>> {
+        let seat = &board.seats[0];
+        if board.gameover || board.pause {
+            return Ok(None);
+        }
+        // the pieces that came into the queue since the bot last heard
+        while self.revealed < seat.pieces {
+            let back = (seat.pieces - self.revealed - 1) as usize;
+            self.revealed += 1;
+            if let Some(fig) = seat.next_figures.iter().rev().nth(back) {
+                let piece = fig.piece_type.letter();
+                self.send(&FrontendMessage::NewPiece { piece })?;
+            }
+        }
+        let Some(fig) = seat.actual_figure else {
+            return Ok(None);
+        };
+        if self.playing != Some(seat.pieces) {
+            // the piece locked before the move was done
+            self.plan.clear();
+            if !self.waiting {
+                self.send(&FrontendMessage::Suggest)?;
+                self.waiting = true;
+            }
+        }
+        if self.waiting {
+            let line = match self.lines.try_recv() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => return Err(bot_error("quit".to_string())),
+            };
+            match decode(&line)? {
+                BotMessage::Suggestion { moves } => self.choose(board, fig, &moves)?,
+                BotMessage::Error { reason } => return Err(bot_error(reason)),
+                _ => return Ok(None),
+            }
+        }
+        if self.wait > 0 {
+            self.wait -= 1;
+            return Ok(None);
+        }
+        self.wait = DELAY;
+        Ok(Some(self.plan.pop_front().unwrap_or(Input::SoftDrop)))
+    }
+
+    // plays the first of `moves` the piece can reach, or drops it straight
+    // when there's none, and tells the bot which one it was
+    fn choose(&mut self, board: &GameState, fig: Figure, moves: &[Move]) -> GameResult {
+        let seat = &board.seats[0];
+        let chosen = moves
+            .iter()
+            .find_map(|mv| Some((mv.clone(), self.inputs_for(board, fig, mv)?)));
+        let (mv, inputs) = match chosen {
+            Some(chosen) => chosen,
+            None => {
+                let location = Location::of(&landing(board, fig), board.grid)
+                    .ok_or_else(|| bot_error("the piece can't land".to_string()))?;
+                let mv = Move {
+                    location,
+                    spin: "none".to_string(),
+                };
+                (mv, vec![])
+            }
+        };
+        // keeping the piece with nothing kept yet takes the next one
+        let held = inputs.first() == Some(&Input::Hold);
+        self.playing = Some(seat.pieces + (held && seat.keep_figure.is_none()) as u64);
+        self.waiting = false;
+        self.plan = inputs.into();
+        self.send(&FrontendMessage::Play { mv })
+    }
+
+    // the inputs that play `mv` with the falling piece `fig`, or with the
+    // kept one
+    fn inputs_for(&self, board: &GameState, fig: Figure, mv: &Move) -> Option<Vec<Input>> {
+        let seat = &board.seats[0];
+        let target = mv.location.cells(board.grid)?;
+        if mv.location.piece == fig.piece_type.letter() {
+            return inputs_to(board, fig, target);
+        }
+        let coming = seat.keep_figure.or(seat.next_figures.first().copied())?;
+        if mv.location.piece != coming.piece_type.letter() {
+            return None;
+        }
+        let mut inputs = inputs_to(board, seat.spawned(coming, board.grid), target)?;
+        inputs.insert(0, Input::Hold);
+        Some(inputs)
+    }
+}
+impl Drop for TbpBot {
+    fn drop(&mut self) {
+        let _ = self.send(&FrontendMessage::Quit);
+        // a bot ignoring `quit` doesn't keep the game from closing
+        let start = Instant::now();
+        while start.elapsed() < QUIT_TIMEOUT {
+            match self.process.try_wait() {
+                Ok(None) => thread::sleep(Duration::from_millis(10)),
+                _ => return,
+            }
+        }
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+fn decode(line: &str) -> GameResult<BotMessage> {
+    serde_json::from_str(line).map_err(|e| bot_error(format!("`{}`: {}", line, e)))
+}
+
+/// A single player game played by an external bot.
+pub struct TbpGame {
+    state: GameState,
+    bot: TbpBot,
+}
+impl TbpGame {
+    pub fn new(command: &[String]) -> GameResult<Self> {
+        let state = GameState::new();
+        let mut bot = TbpBot::spawn(command)?;
+        println!("Playing with {}", bot.name);
+        bot.start(&state)?;
+        Ok(TbpGame { state, bot })
+    }
+}
+
+impl Configurable for TbpGame {}
+
+impl Watched for TbpGame {
+    fn boards(&self) -> Vec<&GameState> {
+        vec![&self.state]
+    }
+}
+
+impl event::EventHandler<GameError> for TbpGame {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        while timer::check_update_time(ctx, FPS) {
+            if self.state.gameover {
+                continue;
+            }
+            if let Some(input) = self.bot.input(&self.state)? {
+                self.state.handle_input(input);
+            }
+            self.state.tick();
+            if self.state.gameover {
+                self.bot.stop()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
+        self.state.draw_board(ctx, &Layout::single())?;
+        graphics::present(ctx)?;
+        timer::yield_now();
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        _repeat: bool,
+    ) {
+        match keycode {
+            KeyCode::R => {
+                if !self.state.gameover {
+                    let _ = self.bot.stop();
+                }
+                self.state.restart();
+                if let Err(e) = self.bot.start(&self.state) {
+                    eprintln!("{}", e);
+                }
+            }
+            KeyCode::P => self.state.pause = !self.state.pause,
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! `tetris --train`: evolves the weights of the bot with a genetic algorithm.
+//! Every generation each set of weights plays the same seeded games, with no
+//! window and on as many threads as there are cores, and the ones clearing the
+//! most lines breed the next generation. The best weights of the generation are
+//! written to a file after each one, the game plays with them when started
+//! with `--weights <file>`.
+//!
+//! ```text
+//! tetris --train [--generations <n>] [--population <n>] [--games <n>] [--pieces <n>] [--out <file>]
+//! ```
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::thread;
+
+use ggez::{GameError, GameResult};
+use oorandom::Rand32;
+
+use crate::bot::{Bot, Weights};
+use crate::{random_seed, GameState};
+
+// Chance of each weight of a child to be mutated, and by how much at most.
+const MUTATION_RATE: f32 = 0.1;
+const MUTATION: f32 = 0.2;
+// Weights picked at random to choose each parent among them.
+const TOURNAMENT: usize = 4;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TrainOptions {
+    pub generations: u32,
+    pub population: usize,
+    // games played by each set of weights every generation
+    pub games: usize,
+    // pieces each game lasts at most, so the good bots don't play forever
+    pub pieces: u64,
+    pub out: String,
+}
+impl Default for TrainOptions {
+    fn default() -> Self {
+        TrainOptions {
+            generations: 20,
+            population: 32,
+            games: 4,
+            pieces: 500,
+            out: "weights.txt".to_string(),
+        }
+    }
+}
+impl TrainOptions {
+    /// Reads the options following `--train`.
+    pub fn parse(args: &[String]) -> GameResult<Self> {
+        let mut options = TrainOptions::default();
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| GameError::ConfigError(format!("{} needs a value", flag)))?;
+            let number = || {
+                value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| GameError::ConfigError(format!("`{}` is not a count", value)))
+            };
+            match flag.as_str() {
+                "--generations" => options.generations = number()? as u32,
+                "--population" => options.population = number()?.max(2),
+                "--games" => options.games = number()?,
+                "--pieces" => options.pieces = number()? as u64,
+                "--out" => options.out = value.clone(),
+                _ => {
+                    return Err(GameError::ConfigError(format!(
+                        "unknown option `{}` for --train",
+                        flag
+                    )))
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+// The weights as a vector, the bot only compares placements so only its
+// direction matters and it's kept one long. The attack isn't in, with no
+// opponent the garbage sent doesn't matter.
+type Genes = [f32; 5];
+
+fn genes(weights: &Weights) -> Genes {
+    normalized([
+        weights.lines,
+        weights.height,
+        weights.holes,
+        weights.bumpiness,
+        weights.wells,
+    ])
+}
+
+fn weights(genes: Genes) -> Weights {
+    let [lines, height, holes, bumpiness, wells] = genes;
+    Weights {
+        lines,
+        height,
+        holes,
+        bumpiness,
+        wells,
+        ..Weights::default()
+    }
+}
+
+fn normalized(genes: Genes) -> Genes {
+    let length = genes.iter().map(|gene| gene * gene).sum::<f32>().sqrt();
+    if length == 0.0 {
+        genes
+    } else {
+        genes.map(|gene| gene / length)
+    }
+}
+
+/// Lines the bot clears with `weights` in the game given by `seed`, playing as
+/// fast as it can until it tops out or has placed `pieces`.
+fn play(weights: Weights, seed: u64, pieces: u64) -> u32 {
+    let mut state = GameState::with_seed(seed);
+    let mut bot = Bot::new(0);
+    bot.weights = weights;
+    while !state.gameover && state.seats[0].pieces < pieces {
+        if let Some(input) = bot.input(&state) {
+            state.handle_input(input);
+        }
+        state.tick();
+    }
+    state.score
+}
+
+/// Lines cleared by each of `population` in all the games of `seeds`. The
+/// threads take the next weights to try as they finish, the bad ones top out
+/// quickly.
+fn fitness(population: &[Genes], seeds: &[u64], pieces: u64) -> Vec<u32> {
+    let next = AtomicUsize::new(0);
+    let lines: Vec<AtomicU32> = population.iter().map(|_| AtomicU32::new(0)).collect();
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let n = next.fetch_add(1, Ordering::Relaxed);
+                let Some(genes) = population.get(n) else {
+                    break;
+                };
+                let total = seeds
+                    .iter()
+                    .map(|seed| play(weights(*genes), *seed, pieces))
+                    .sum();
+                lines[n].store(total, Ordering::Relaxed);
+            });
+        }
+    });
+    lines.into_iter().map(AtomicU32::into_inner).collect()
+}
+
+/// The best of a few members of the population picked at random.
+fn tournament(fitness: &[u32], rng: &mut Rand32) -> usize {
+    (0..TOURNAMENT)
+        .map(|_| rng.rand_range(0..fitness.len() as u32) as usize)
+        .max_by_key(|n| fitness[*n])
+        .unwrap_or(0)
+}
+
+/// The next generation: the best quarter goes on as it is and the others are
+/// children of two parents, weighted towards the better one and mutated a bit.
+fn breed(population: &[Genes], fitness: &[u32], rng: &mut Rand32) -> Vec<Genes> {
+    let mut ranked: Vec<usize> = (0..population.len()).collect();
+    ranked.sort_by_key(|n| std::cmp::Reverse(fitness[*n]));
+    let mut next: Vec<Genes> = ranked
+        .iter()
+        .take(population.len().div_ceil(4))
+        .map(|n| population[*n])
+        .collect();
+    while next.len() < population.len() {
+        let (a, b) = (tournament(fitness, rng), tournament(fitness, rng));
+        let (fitness_a, fitness_b) = (fitness[a] as f32 + 1.0, fitness[b] as f32 + 1.0);
+        let child = std::array::from_fn(|k| {
+            let gene = (population[a][k] * fitness_a + population[b][k] * fitness_b)
+                / (fitness_a + fitness_b);
+            if rng.rand_float() < MUTATION_RATE {
+                gene + (rng.rand_float() * 2.0 - 1.0) * MUTATION
+            } else {
+                gene
+            }
+        });
+        next.push(normalized(child));
+    }
+    next
+}
+
+/// Runs the training, printing how each generation did.
+pub fn train(options: &TrainOptions) -> GameResult {
+    let mut rng = Rand32::new(random_seed());
+    // the default weights are in from the start, the rest are random
+    let mut population: Vec<Genes> = (0..options.population)
+        .map(|_| normalized(std::array::from_fn(|_| rng.rand_float() * 2.0 - 1.0)))
+        .collect();
+    population[0] = genes(&Weights::default());
+    for generation in 1..=options.generations {
+        // new games every generation, so the weights don't just learn the seeds
+        let seeds: Vec<u64> = (0..options.games)
+            .map(|_| (rng.rand_u32() as u64) << 32 | rng.rand_u32() as u64)
+            .collect();
+        let fitness = fitness(&population, &seeds, options.pieces);
+        let (n, lines) = fitness
+            .iter()
+            .copied()
+            .enumerate()
+            .max_by_key(|(_, lines)| *lines)
+            .unwrap_or((0, 0));
+        let average = fitness.iter().sum::<u32>() as f32 / fitness.len() as f32;
+        println!(
+            "generation {}/{}: best {} lines, average {:.1}, {:.3?}",
+            generation,
+            options.generations,
+            lines,
+            average,
+            weights(population[n])
+        );
+        // the best of this generation's games, replacing the last one's: the
+        // best ones go on and play the new seeds too, so the file ends up with
+        // the best of the last generation rather than of all the games played
+        weights(population[n]).save(&options.out)?;
+        population = breed(&population, &fitness, &mut rng);
+    }
+    println!("best weights written to {}", options.out);
+    Ok(())
+}
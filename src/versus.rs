@@ -0,0 +1,206 @@
+//! Two players on one machine, each with their own board, sending each other
+//! garbage when they clear lines. The right player can be a bot instead, of
+//! the difficulty picked with the keys 1 to 4.
+use ggez::event::{self, Button, GamepadId, KeyCode, KeyMods};
+use ggez::graphics::{self, DrawParam, Font, PxScale, Text};
+use ggez::{timer, Context, GameResult};
+use mint::Point2;
+
+use crate::bot::{Bot, Difficulty};
+use crate::input::{Input, KeyBindings};
+use crate::settings::Configurable;
+use crate::spectate::Watched;
+use crate::{random_seed, GameState, Layout, FPS, SCREEN_SIZE};
+
+struct Player {
+    state: GameState,
+    keys: KeyBindings,
+    layout: Layout,
+    // the first gamepad used after the game starts belongs to the left player,
+    // the next one to the right player
+    gamepad: Option<GamepadId>,
+    // playing instead of a person
+    bot: Option<Bot>,
+}
+
+pub struct Versus {
+    players: [Player; 2],
+    // the level of the right player when it's a bot
+    difficulty: Option<Difficulty>,
+}
+impl Versus {
+    pub fn new() -> Self {
+        let player = |keys, n| Player {
+            state: GameState::new(),
+            keys,
+            layout: Layout::versus(n),
+            gamepad: None,
+            bot: None,
+        };
+        Versus {
+            players: [
+                player(KeyBindings::wasd(), 0),
+                player(KeyBindings::arrows_right_hand(), 1),
+            ],
+            difficulty: None,
+        }
+    }
+
+    /// The player, with the single player keys, against a bot on the right.
+    pub fn against(difficulty: Difficulty) -> Self {
+        let mut versus = Versus::new();
+        versus.players[0].keys = KeyBindings::arrows();
+        versus.set_difficulty(difficulty);
+        versus
+    }
+
+    fn set_difficulty(&mut self, difficulty: Difficulty) {
+        self.difficulty = Some(difficulty);
+        self.players[1].bot = Some(difficulty.bot(random_seed()));
+    }
+
+    /// Once a player tops out the other one wins.
+    fn winner(&self) -> Option<usize> {
+        self.players
+            .iter()
+            .position(|p| p.state.gameover)
+            .map(|loser| 1 - loser)
+    }
+}
+
+impl Configurable for Versus {}
+
+impl Watched for Versus {
+    fn boards(&self) -> Vec<&GameState> {
+        self.players.iter().map(|p| &p.state).collect()
+    }
+}
+
+impl event::EventHandler<ggez::GameError> for Versus {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        while timer::check_update_time(ctx, FPS) {
+            if self.winner().is_some() {
+                continue;
+            }
+            for player in self.players.iter_mut() {
+                if let Some(input) = player.bot.as_mut().and_then(|bot| bot.input(&player.state)) {
+                    player.state.handle_input(input);
+                }
+                player.state.tick();
+            }
+            // what each one sent this frame goes to the other one
+            let sent = [
+                std::mem::take(&mut self.players[0].state.garbage.outgoing),
+                std::mem::take(&mut self.players[1].state.garbage.outgoing),
+            ];
+            for (player, lines) in self.players.iter_mut().zip(sent.iter().rev()) {
+                player.state.garbage.receive(*lines, player.state.ticks);
+            }
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
+        for player in self.players.iter() {
+            player.state.draw_board(ctx, &player.layout)?;
+        }
+        if let Some(difficulty) = self.difficulty {
+            let mut text = Text::new(format!(
+                "CPU : {}\n1 Easy  2 Medium  3 Hard  4 Expert",
+                difficulty.name()
+            ));
+            text.set_font(Font::default(), PxScale::from(32.0));
+            graphics::draw(
+                ctx,
+                &text,
+                DrawParam::default().dest(Point2 { x: 10.0, y: 10.0 }),
+            )?;
+        }
+        match (self.winner(), self.difficulty) {
+            (Some(0), Some(_)) => draw_banner(ctx, "You win !  press R for a rematch")?,
+            (Some(_), Some(difficulty)) => draw_banner(
+                ctx,
+                &format!("{} CPU wins !  press R for a rematch", difficulty.name()),
+            )?,
+            (Some(winner), None) => draw_banner(
+                ctx,
+                &format!("Player {} wins !  press R for a rematch", winner + 1),
+            )?,
+            (None, _) => (),
+        }
+        graphics::present(ctx)?;
+        timer::yield_now();
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        _repeat: bool,
+    ) {
+        match keycode {
+            KeyCode::R => {
+                for player in self.players.iter_mut() {
+                    player.state.restart();
+                }
+            }
+            KeyCode::P => {
+                for player in self.players.iter_mut() {
+                    player.state.pause = !player.state.pause;
+                }
+            }
+            KeyCode::Key1 | KeyCode::Key2 | KeyCode::Key3 | KeyCode::Key4
+                if self.difficulty.is_some() =>
+            {
+                let n = match keycode {
+                    KeyCode::Key1 => 0,
+                    KeyCode::Key2 => 1,
+                    KeyCode::Key3 => 2,
+                    _ => 3,
+                };
+                self.set_difficulty(Difficulty::ALL[n]);
+            }
+            _ => {
+                for player in self.players.iter_mut().filter(|p| p.bot.is_none()) {
+                    if let Some(input) = player.keys.input(keycode) {
+                        player.state.handle_input(input);
+                    }
+                }
+            }
+        }
+    }
+
+    fn gamepad_button_down_event(&mut self, _ctx: &mut Context, btn: Button, id: GamepadId) {
+        if !self.players.iter().any(|p| p.gamepad == Some(id)) {
+            let free = self
+                .players
+                .iter_mut()
+                .find(|p| p.gamepad.is_none() && p.bot.is_none());
+            if let Some(player) = free {
+                player.gamepad = Some(id);
+            }
+        }
+        let player = self.players.iter_mut().find(|p| p.gamepad == Some(id));
+        if let (Some(player), Some(input)) = (player, Input::from_button(btn)) {
+            player.state.handle_input(input);
+        }
+    }
+}
+
+/// Draws `message` centered under the boards, for the end of a match.
+pub fn draw_banner(ctx: &mut Context, message: &str) -> GameResult {
+    let mut text = Text::new(message);
+    text.set_font(Font::default(), PxScale::from(48.0));
+    let width = text.width(ctx);
+    graphics::draw(
+        ctx,
+        &text,
+        DrawParam::default().dest(Point2 {
+            x: (SCREEN_SIZE.0 - width) / 2.0,
+            y: SCREEN_SIZE.1 - 64.0,
+        }),
+    )
+}
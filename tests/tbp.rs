@@ -0,0 +1,75 @@
+//! Plays the mock bot of `examples/tbp_mock_bot.rs` through the Tetris Bot
+//! Protocol. `cargo test` builds the examples along with the tests.
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use tetris::tbp::TbpBot;
+use tetris::GameState;
+
+const ROWS: i16 = 20;
+
+fn mock_bot() -> String {
+    // the tests run from `target/<profile>/deps`, the examples are next to it
+    let mut path: PathBuf = std::env::current_exe().unwrap();
+    path.pop();
+    path.pop();
+    path.push("examples");
+    path.push(format!("tbp_mock_bot{}", std::env::consts::EXE_SUFFIX));
+    assert!(
+        path.exists(),
+        "build the mock bot first with `cargo build --example tbp_mock_bot`"
+    );
+    path.to_string_lossy().into_owned()
+}
+
+// where the mock bot drops `piece` on an empty board: pointing north, in the
+// first column it fits in, on the floor
+fn first_suggestion(piece: char) -> Vec<(i16, i16)> {
+    let offsets = match piece {
+        'I' => [(-1, 0), (0, 0), (1, 0), (2, 0)],
+        'O' => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        'T' => [(-1, 0), (0, 0), (1, 0), (0, 1)],
+        'L' => [(-1, 0), (0, 0), (1, 0), (1, 1)],
+        'J' => [(-1, 0), (0, 0), (1, 0), (-1, 1)],
+        'S' => [(-1, 0), (0, 0), (0, 1), (1, 1)],
+        _ => [(-1, 1), (0, 1), (0, 0), (1, 0)],
+    };
+    let x = -offsets.iter().map(|(dx, _)| *dx).min().unwrap();
+    let mut cells: Vec<(i16, i16)> = offsets
+        .iter()
+        .map(|(dx, dy)| (x + dx, ROWS - 1 - dy))
+        .collect();
+    cells.sort();
+    cells
+}
+
+#[test]
+fn the_piece_locks_where_the_bot_said() {
+    // `spawn` goes through `info`, `rules` and `ready`
+    let mut bot = TbpBot::spawn(&[mock_bot()]).unwrap();
+    assert_eq!(bot.name, "Mock 1.0");
+    let mut board = GameState::with_seed(2);
+    bot.start(&board).unwrap();
+    let mut piece = None;
+    for _ in 0..10000 {
+        // the first piece asks for a `suggest` and gets played with `play`,
+        // the game waits for the suggestion instead of letting the piece fall
+        let input = bot.input(&board).unwrap();
+        if bot.thinking() {
+            thread::sleep(Duration::from_millis(1));
+            continue;
+        }
+        if let Some(input) = input {
+            board.handle_input(input);
+        }
+        board.tick();
+        piece = piece.or(board.falling_piece());
+        if !board.stack_cells().is_empty() {
+            break;
+        }
+    }
+    let mut cells = board.stack_cells();
+    cells.sort();
+    assert_eq!(cells, first_suggestion(piece.unwrap()));
+}